@@ -0,0 +1,189 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use ncurses::beep;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The default number of bells per second
+pub const BELL_RATE: usize = 2;
+
+/// The longest time that an event waits for the rate limit, a later
+/// bell would not belong to it anymore
+const BELL_WAIT: Duration = Duration::from_millis(250);
+
+/// Events that ring the terminal bell, from the lowest to highest priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    /// A block was locked on the stack
+    Lock,
+    /// One to three rows were cleared
+    Clear,
+    /// Four rows were cleared at once
+    Tetris,
+    /// The game has ended
+    GameOver,
+}
+
+impl FromStr for Event {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lock" => Ok(Event::Lock),
+            "clear" => Ok(Event::Clear),
+            "tetris" => Ok(Event::Tetris),
+            "gameover" => Ok(Event::GameOver),
+            _ => Err(format!("unknown bell event: {}", s)),
+        }
+    }
+}
+
+/// The rate-limited terminal bell
+pub struct Bell {
+    /// Events that are allowed to ring the bell
+    enabled: Vec<Event>,
+    /// The maximum number of bells per second
+    rate: usize,
+    /// The bells that were rung during the last second
+    rung: VecDeque<(Instant, Event)>,
+    /// The most important event that did not ring yet, and when it
+    /// happened
+    pending: Option<(Instant, Event)>,
+}
+
+impl Bell {
+    /// Create a bell that rings for all events
+    pub fn new() -> Self {
+        Self {
+            enabled: vec![Event::Lock, Event::Clear, Event::Tetris, Event::GameOver],
+            rate: BELL_RATE,
+            rung: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    /// Never ring the bell for the specified event
+    pub fn disable(&mut self, event: Event) {
+        self.enabled.retain(|e| *e != event);
+    }
+
+    /// Set the maximum number of bells per second
+    pub fn set_rate(&mut self, rate: usize) {
+        self.rate = rate;
+    }
+
+    /// Queue an event, it replaces a less important event that waits
+    /// to ring
+    pub fn push(&mut self, event: Event) {
+        self.queue(Instant::now(), event);
+    }
+
+    /// Queue an event that happened at the specified time
+    fn queue(&mut self, now: Instant, event: Event) {
+        if !self.enabled.contains(&event) {
+            return;
+        }
+        match self.pending {
+            Some((time, pending)) if pending >= event && now.duration_since(time) <= BELL_WAIT => {}
+            _ => self.pending = Some((now, event)),
+        }
+    }
+
+    /// Ring the bell for the waiting event, if the rate limit allows it
+    pub fn ring(&mut self) {
+        if self.next(Instant::now()).is_some() {
+            beep();
+        }
+    }
+
+    /// Take the event that may ring now.  The bell never rings more
+    /// often than the rate, an event that waited too long is dropped.
+    fn next(&mut self, now: Instant) -> Option<Event> {
+        while let Some((time, _)) = self.rung.front() {
+            if now.duration_since(*time) < Duration::from_secs(1) {
+                break;
+            }
+            self.rung.pop_front();
+        }
+
+        let (time, event) = self.pending?;
+        if now.duration_since(time) > BELL_WAIT {
+            self.pending = None;
+            return None;
+        }
+        if self.rung.len() >= self.rate {
+            return None;
+        }
+
+        self.pending = None;
+        self.rung.push_back((now, event));
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rings_at_the_rate() {
+        let mut bell = Bell::new();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        bell.queue(at(0), Event::Lock);
+        assert_eq!(bell.next(at(0)), Some(Event::Lock));
+        bell.queue(at(10), Event::Lock);
+        assert_eq!(bell.next(at(10)), Some(Event::Lock));
+
+        // The limit is reached, even for a more important event
+        bell.queue(at(20), Event::Tetris);
+        assert_eq!(bell.next(at(20)), None);
+
+        // The most important waiting event rings when a bell expired
+        bell.queue(at(900), Event::Lock);
+        bell.queue(at(950), Event::Tetris);
+        bell.queue(at(960), Event::Clear);
+        assert_eq!(bell.next(at(999)), None);
+        assert_eq!(bell.next(at(1000)), Some(Event::Tetris));
+        assert_eq!(bell.next(at(1000)), None);
+    }
+
+    #[test]
+    fn drops_late_events() {
+        let mut bell = Bell::new();
+        bell.set_rate(1);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        bell.queue(at(0), Event::Clear);
+        assert_eq!(bell.next(at(0)), Some(Event::Clear));
+        bell.queue(at(100), Event::GameOver);
+        assert_eq!(bell.next(at(500)), None);
+        assert_eq!(bell.next(at(1000)), None);
+    }
+
+    #[test]
+    fn ignores_disabled_events() {
+        let mut bell = Bell::new();
+        bell.disable(Event::Lock);
+        let now = Instant::now();
+        bell.queue(now, Event::Lock);
+        assert_eq!(bell.next(now), None);
+    }
+}
@@ -0,0 +1,243 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::game::{GameOver, GameState};
+use crate::piece::Rotation;
+use crate::replay::Input;
+
+/// The inputs that turn the piece into each of its four rotation states
+const ROTATIONS: [Option<Rotation>; 4] = [
+    None,
+    Some(Rotation::Clockwise),
+    Some(Rotation::Half),
+    Some(Rotation::CounterClockwise),
+];
+
+/// A simple bot that tries every rotation and column of the piece and
+/// picks the placement that leaves the best looking field
+#[derive(Debug, Clone, Copy)]
+pub struct Bot {
+    /// The weight of the sum of the column heights
+    height: f64,
+    /// The weight of the cleared lines
+    lines: f64,
+    /// The weight of the empty cells below the top of each column
+    holes: f64,
+    /// The weight of the height differences between the columns
+    bumpiness: f64,
+}
+
+impl Default for Bot {
+    /// The weights that were tuned by Yiyuan Lee's genetic algorithm
+    fn default() -> Self {
+        Self {
+            height: -0.510_066,
+            lines: 0.760_666,
+            holes: -0.356_63,
+            bumpiness: -0.184_483,
+        }
+    }
+}
+
+impl Bot {
+    /// Rate the field of the game after a piece cleared the lines,
+    /// higher is better
+    pub fn evaluate(&self, state: &GameState, lines: usize) -> f64 {
+        let (height, width) = (state.height(), state.width());
+        let mut heights = Vec::with_capacity(width as usize);
+        let mut holes = 0;
+        for x in 0..width {
+            let top = (0..height)
+                .find(|y| state.cell(*y, x) != 0)
+                .unwrap_or(height);
+            holes += (top..height).filter(|y| state.cell(*y, x) == 0).count();
+            heights.push(height - top);
+        }
+        let bumpiness: i32 = heights.windows(2).map(|h| (h[0] - h[1]).abs()).sum();
+        let height: i32 = heights.iter().sum();
+
+        self.height * f64::from(height)
+            + self.lines * lines as f64
+            + self.holes * holes as f64
+            + self.bumpiness * f64::from(bumpiness)
+    }
+
+    /// Find the best placement of the falling piece, or of the piece
+    /// from the hold slot, and return its score and the inputs that
+    /// move the piece there and drop it
+    fn search(&self, state: &GameState) -> Option<(f64, Vec<Input>)> {
+        let mut best: Option<(f64, Vec<Input>)> = None;
+
+        for rotation in ROTATIONS.iter() {
+            for column in 0..state.width() {
+                let mut inputs: Vec<Input> = match rotation {
+                    Some(Rotation::Half) if !state.rules().half() => {
+                        vec![Input::Rotate(Rotation::Clockwise); 2]
+                    }
+                    _ => rotation.map(Input::Rotate).into_iter().collect(),
+                };
+                inputs.push(Input::Column(column));
+
+                // Play the inputs on a copy of the game, skipping columns
+                // that the piece cannot reach
+                let mut copy = state.clone();
+                for input in inputs.iter() {
+                    copy.apply(*input);
+                }
+                let piece = copy.piece();
+                if piece.x + piece.left() != column {
+                    continue;
+                }
+                // The classic rules have no hard drop, the piece is
+                // moved down row by row until it locks
+                let drop = if state.rules().hard_drop() {
+                    Input::HardDrop
+                } else {
+                    while copy.down() {
+                        inputs.push(Input::Down);
+                    }
+                    Input::Lock
+                };
+                inputs.push(drop);
+                let lines = copy.apply(drop).unwrap_or_default();
+
+                // Reaching the goal is the best placement and topping
+                // out the worst of all
+                let score = match copy.done() {
+                    Some(GameOver::Finished) => f64::MAX,
+                    Some(_) => f64::MIN,
+                    None => self.evaluate(&copy, lines),
+                };
+                let better = match best.as_ref() {
+                    Some((best, _)) => score > *best,
+                    None => true,
+                };
+                if better {
+                    best = Some((score, inputs));
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Plan the inputs for the falling piece, which may swap it with the
+    /// hold slot first if the other piece fits better
+    pub fn plan(&self, state: &GameState) -> Vec<Input> {
+        if state.done().is_some() || state.paused() {
+            return Vec::new();
+        }
+        let best = self.search(state);
+
+        let mut held = state.clone();
+        let hold = if held.swap() {
+            self.search(&held).map(|(score, mut inputs)| {
+                inputs.insert(0, Input::Hold);
+                (score, inputs)
+            })
+        } else {
+            None
+        };
+
+        match (best, hold) {
+            (Some((score, _)), Some((held, inputs))) if held > score => inputs,
+            (Some((_, inputs)), _) | (None, Some((_, inputs))) => inputs,
+            (None, None) if state.rules().hard_drop() => vec![Input::HardDrop],
+            (None, None) => vec![Input::Lock],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Rules, GARBAGE};
+
+    /// Start a game with a fixed sequence of pieces and the rows of a
+    /// stack at the bottom, '#' is a garbage cell
+    fn game(pieces: &str, stack: &[&str]) -> GameState {
+        let mut game = GameState::with_seed(0);
+        let names: Vec<char> = pieces.chars().collect();
+        assert!(game.set_sequence(&names));
+        let mut cells = vec![0; game.cells().len()];
+        let start = cells.len() - stack.len() * game.width() as usize;
+        for (i, c) in stack.concat().chars().enumerate() {
+            cells[start + i] = if c == '#' { GARBAGE } else { 0 };
+        }
+        assert!(game.set_cells(&cells));
+        game
+    }
+
+    /// Play the inputs of the plan
+    fn play(game: &mut GameState, inputs: &[Input]) {
+        for input in inputs {
+            game.apply(*input);
+        }
+    }
+
+    #[test]
+    fn evaluates_the_field() {
+        let bot = Bot::default();
+        assert_eq!(bot.evaluate(&game("I", &[]), 0), 0.0);
+        let flat = bot.evaluate(&game("I", &["##########"]), 0);
+        let hole = bot.evaluate(&game("I", &["#.########", "##########"]), 0);
+        assert!(flat > hole);
+        assert!(bot.evaluate(&game("I", &[]), 1) > 0.0);
+    }
+
+    #[test]
+    fn plans_a_tetris() {
+        let mut game = game("IO", &["#########."; 4]);
+        let inputs = Bot::default().plan(&game);
+        assert_eq!(inputs.last(), Some(&Input::HardDrop));
+        play(&mut game, &inputs);
+        assert_eq!(game.lines(), 4);
+        assert!(game.cells().iter().all(|id| *id == 0));
+    }
+
+    #[test]
+    fn holds_the_better_piece() {
+        // The O piece does not fit into the well, the I piece from the
+        // hold slot clears the rows
+        let mut game = game("OIO", &["#########."; 4]);
+        let inputs = Bot::default().plan(&game);
+        assert_eq!(inputs.first(), Some(&Input::Hold));
+        play(&mut game, &inputs);
+        assert_eq!(game.lines(), 4);
+    }
+
+    #[test]
+    fn plans_by_the_classic_rules() {
+        let mut game = game("II", &["#########."; 4]);
+        game.set_rules(Rules::Classic);
+        let inputs = Bot::default().plan(&game);
+        assert!(!inputs.contains(&Input::HardDrop));
+        assert!(!inputs.contains(&Input::Hold));
+        assert_eq!(inputs.last(), Some(&Input::Lock));
+        play(&mut game, &inputs);
+        assert_eq!(game.lines(), 4);
+    }
+
+    #[test]
+    fn waits_while_paused() {
+        let mut game = game("I", &[]);
+        game.apply(Input::Pause);
+        assert!(Bot::default().plan(&game).is_empty());
+        game.apply(Input::Pause);
+        game.apply(Input::Quit);
+        assert!(Bot::default().plan(&game).is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::record::escape;
+use crate::Setup;
+use rand::{thread_rng, Rng};
+use retris::{GameState, Input, Piece};
+use std::io::{self, BufRead, Write};
+
+/// Get the letter of a piece as a JSON string
+fn name(piece: &Piece) -> String {
+    escape(&piece.name().to_string())
+}
+
+/// Get the state of the game as a JSON object, with the number of rows
+/// that the last command cleared
+fn frame(state: &GameState, cleared: usize) -> String {
+    let field: Vec<String> = state
+        .cells()
+        .chunks(state.width() as usize)
+        .map(|row| {
+            let row: String = row
+                .iter()
+                .map(|id| if *id == 0 { '.' } else { state.name(*id) })
+                .collect();
+            escape(&row)
+        })
+        .collect();
+    let piece = state.piece();
+    let cells: Vec<String> = piece
+        .pixels()
+        .map(|(y, x)| format!("[{},{}]", y, x))
+        .collect();
+    let next: Vec<String> = state.queue().map(name).collect();
+    let hold = state.hold().map_or_else(|| "null".to_string(), name);
+    let gameover = state
+        .done()
+        .map_or_else(|| "null".to_string(), |reason| escape(&reason.to_string()));
+
+    format!(
+        "{{\"seed\":{},\"field\":[{}],\"piece\":{{\"name\":{},\"cells\":[{}]}},\
+         \"next\":[{}],\"hold\":{},\"score\":{},\"lines\":{},\"level\":{},\
+         \"cleared\":{},\"gameover\":{}}}",
+        state.seed(),
+        field.join(","),
+        name(piece),
+        cells.join(","),
+        next.join(","),
+        hold,
+        state.score(),
+        state.lines(),
+        state.level(),
+        cleared,
+        gameover
+    )
+}
+
+/// Apply a command to the game and get the reply, the state or an error
+fn command(state: &mut GameState, command: &str) -> String {
+    match command.parse() {
+        // Rules without a hard drop lock a piece on the ground instead
+        Ok(input) if Input::moves(input) || input == Input::Lock || input == Input::Quit => {
+            let cleared = state.apply(input).unwrap_or_default();
+            frame(state, cleared)
+        }
+        Ok(_) => format!(
+            "{{\"error\":{}}}",
+            escape(&format!("not a move: {}", command))
+        ),
+        Err(err) => format!("{{\"error\":{}}}", escape(&err)),
+    }
+}
+
+/// Play a game without the terminal: the state is written as a line of
+/// JSON to stdout after every command from stdin, until the game is
+/// over.  The commands are the moves of a replay and lock, time does
+/// not pass and pieces only lock when they are dropped or locked on the
+/// ground.
+pub fn bot_io(setup: Setup) -> io::Result<()> {
+    let seed = setup.seed.unwrap_or_else(|| thread_rng().gen());
+    let mut state = GameState::with_size(seed, setup.height, setup.width);
+    state.set_rules(setup.rules);
+    state.set_mode(setup.mode);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "{}", frame(&state, 0))?;
+    out.flush()?;
+
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let reply = command(&mut state, line);
+        writeln!(out, "{}", reply)?;
+        out.flush()?;
+
+        if state.done().is_some() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use retris::Rules;
+
+    #[test]
+    fn locks_without_a_hard_drop() {
+        let mut state = GameState::with_seed(3);
+        state.set_rules(Rules::Classic);
+        let piece = state.piece().name();
+
+        // The classic rules ignore a hard drop, the piece locks on the floor
+        command(&mut state, "drop");
+        assert_eq!(state.stats().pieces(), 0);
+        for _ in 0..state.height() {
+            command(&mut state, "down");
+        }
+        assert!(state.grounded());
+        command(&mut state, "lock");
+        assert_eq!(state.stats().pieces(), 1);
+        let bottom = (state.height() - 1) * state.width();
+        let bottom = &state.cells()[bottom as usize..];
+        assert!(bottom.iter().any(|id| state.name(*id) == piece));
+    }
+
+    #[test]
+    fn writes_the_state() {
+        let mut state = GameState::with_seed(3);
+        let field = vec!["\"..........\""; 20].join(",");
+        let start = |gameover| {
+            format!(
+                "{{\"seed\":3,\"field\":[{}],\
+                 \"piece\":{{\"name\":\"S\",\"cells\":[[-1,4],[-1,5],[0,3],[0,4]]}},\
+                 \"next\":[\"O\",\"Z\",\"I\",\"J\",\"T\"],\"hold\":null,\
+                 \"score\":0,\"lines\":0,\"level\":1,\"cleared\":0,\"gameover\":{}}}",
+                field, gameover
+            )
+        };
+        assert_eq!(frame(&state, 0), start("null"));
+
+        let reply = command(&mut state, "hold");
+        assert!(reply.contains("\"piece\":{\"name\":\"O\""), "{}", reply);
+        assert!(reply.contains("\"hold\":\"S\""), "{}", reply);
+        let mut state = GameState::with_seed(3);
+        assert_eq!(command(&mut state, "quit"), start("\"Quit\""));
+    }
+
+    #[test]
+    fn replies_with_errors() {
+        let mut state = GameState::with_seed(3);
+        assert_eq!(
+            command(&mut state, "jump"),
+            "{\"error\":\"unknown input: jump\"}"
+        );
+        assert_eq!(
+            command(&mut state, "step"),
+            "{\"error\":\"not a move: step\"}"
+        );
+        assert_eq!(
+            command(&mut state, "garbage 2 3"),
+            "{\"error\":\"not a move: garbage 2 3\"}"
+        );
+        assert_eq!(
+            command(&mut state, "column \"x"),
+            "{\"error\":\"invalid column: \\\"x\"}"
+        );
+        assert_eq!(state.piece().y, -1);
+    }
+}
@@ -0,0 +1,75 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use std::time::{Duration, Instant};
+
+/// The time of a game that stands still while the game is paused
+pub struct Clock {
+    /// The start of the game
+    start: Instant,
+    /// When the game was paused, if it is paused
+    paused: Option<Instant>,
+    /// The time that the game was paused in total
+    pauses: Duration,
+    /// The time of the game before the clock was started
+    base: Duration,
+}
+
+impl Clock {
+    /// Start the clock
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            paused: None,
+            pauses: Duration::default(),
+            base: Duration::default(),
+        }
+    }
+
+    /// Start the clock at the time of a game that was played before
+    pub fn at(time: Duration) -> Self {
+        Self {
+            base: time,
+            ..Self::new()
+        }
+    }
+
+    /// Get the time since the start, without the pauses
+    pub fn now(&self) -> Duration {
+        let now = self.paused.unwrap_or_else(Instant::now);
+        self.base + now.duration_since(self.start) - self.pauses
+    }
+
+    /// Stop the clock
+    pub fn pause(&mut self) {
+        if self.paused.is_none() {
+            self.paused = Some(Instant::now());
+        }
+    }
+
+    /// Continue where the clock was stopped
+    pub fn resume(&mut self) {
+        if let Some(paused) = self.paused.take() {
+            self.pauses += paused.elapsed();
+        }
+    }
+}
+
+/// Format a time as minutes, seconds and hundredths
+pub fn minutes(time: Duration) -> String {
+    let ms = time.as_millis();
+    format!("{}:{:02}.{:02}", ms / 60_000, ms / 1000 % 60, ms / 10 % 100)
+}
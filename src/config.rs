@@ -0,0 +1,594 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::shift::{ARR, DAS};
+use crate::LINE_CLEAR;
+use ncurses::*;
+use retris::{LEVEL_MAX, LOCK_DELAY};
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Names of the special keys that can be bound
+const KEY_NAMES: [(&str, i32); 15] = [
+    ("space", 32),
+    ("enter", 10),
+    ("tab", 9),
+    ("escape", 27),
+    ("backspace", 127),
+    ("left", KEY_LEFT),
+    ("right", KEY_RIGHT),
+    ("up", KEY_UP),
+    ("down", KEY_DOWN),
+    ("home", KEY_HOME),
+    ("end", KEY_END),
+    ("insert", KEY_IC),
+    ("delete", KEY_DC),
+    ("pageup", KEY_PPAGE),
+    ("pagedown", KEY_NPAGE),
+];
+
+/// The actions of the game that can be bound to keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Move the piece to the left
+    MoveLeft,
+    /// Move the piece to the right
+    MoveRight,
+    /// Move the piece one row down
+    SoftDrop,
+    /// Drop the piece and lock it
+    HardDrop,
+    /// Rotate the piece clockwise
+    RotateCw,
+    /// Rotate the piece counter-clockwise
+    RotateCcw,
+    /// Rotate the piece by 180 degrees
+    Rotate180,
+    /// Swap the piece with the hold slot
+    Hold,
+    /// Pause or resume the game
+    Pause,
+    /// Start a new game
+    Restart,
+    /// Save the game to resume it later and leave it
+    Save,
+    /// End the game
+    Quit,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "move_left" => Ok(Action::MoveLeft),
+            "move_right" => Ok(Action::MoveRight),
+            "soft_drop" => Ok(Action::SoftDrop),
+            "hard_drop" => Ok(Action::HardDrop),
+            "rotate_cw" => Ok(Action::RotateCw),
+            "rotate_ccw" => Ok(Action::RotateCcw),
+            "rotate_180" => Ok(Action::Rotate180),
+            "hold" => Ok(Action::Hold),
+            "pause" => Ok(Action::Pause),
+            "restart" => Ok(Action::Restart),
+            "save" => Ok(Action::Save),
+            "quit" => Ok(Action::Quit),
+            _ => Err(format!("unknown action: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::SoftDrop => "soft_drop",
+            Action::HardDrop => "hard_drop",
+            Action::RotateCw => "rotate_cw",
+            Action::RotateCcw => "rotate_ccw",
+            Action::Rotate180 => "rotate_180",
+            Action::Hold => "hold",
+            Action::Pause => "pause",
+            Action::Restart => "restart",
+            Action::Save => "save",
+            Action::Quit => "quit",
+        };
+        f.pad(name)
+    }
+}
+
+/// Get the key code of a key name, or of a single character
+fn key(name: &str) -> Option<i32> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_graphic() => Some(c as i32),
+        _ => KEY_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, k)| *k),
+    }
+}
+
+/// Get the name of a key code
+fn key_name(key: i32) -> String {
+    KEY_NAMES
+        .iter()
+        .find(|(_, k)| *k == key)
+        .map_or_else(|| (key as u8 as char).to_string(), |(n, _)| n.to_string())
+}
+
+/// Check if a key has a name that can be written to the config file
+pub fn bindable(code: i32) -> bool {
+    key(&key_name(code)) == Some(code)
+}
+
+/// The keys that are bound to each action
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    keys: Vec<(Action, Vec<i32>)>,
+}
+
+impl Keymap {
+    /// Bind the keys to an action, taking them away from other actions
+    pub fn bind(&mut self, action: Action, keys: Vec<i32>) {
+        for (_, bound) in self.keys.iter_mut() {
+            bound.retain(|k| !keys.contains(k));
+        }
+        match self.keys.iter_mut().find(|(a, _)| *a == action) {
+            Some((_, bound)) => *bound = keys,
+            None => self.keys.push((action, keys)),
+        }
+    }
+
+    /// Get the action that is bound to a key
+    pub fn action(&self, key: i32) -> Option<Action> {
+        self.keys
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    /// Get all actions and the keys that are bound to them
+    pub fn bindings(&self) -> impl Iterator<Item = (Action, &[i32])> {
+        self.keys
+            .iter()
+            .map(|(action, keys)| (*action, keys.as_slice()))
+    }
+
+    /// Get the name of the first key that is bound to an action
+    pub fn name(&self, action: Action) -> String {
+        self.keys
+            .iter()
+            .find(|(a, _)| *a == action)
+            .and_then(|(_, keys)| keys.first())
+            .map_or_else(|| "-".to_string(), |key| key_name(*key))
+    }
+
+    /// The key bindings of the two players of a versus game, the left
+    /// player uses WASD and the right player the arrow keys
+    pub fn versus() -> [Self; 2] {
+        let common = [
+            (Action::Pause, vec!['p' as i32]),
+            (Action::Restart, vec!['r' as i32]),
+            (Action::Quit, vec!['q' as i32]),
+        ];
+        let left = [
+            (Action::MoveLeft, vec!['a' as i32]),
+            (Action::MoveRight, vec!['d' as i32]),
+            (Action::SoftDrop, vec!['s' as i32]),
+            (Action::HardDrop, vec![32]),
+            (Action::RotateCw, vec!['w' as i32]),
+            (Action::RotateCcw, vec!['z' as i32]),
+            (Action::Rotate180, vec!['e' as i32]),
+            (Action::Hold, vec!['c' as i32]),
+        ];
+        let right = [
+            (Action::MoveLeft, vec![KEY_LEFT]),
+            (Action::MoveRight, vec![KEY_RIGHT]),
+            (Action::SoftDrop, vec![KEY_DOWN]),
+            (Action::HardDrop, vec![10]),
+            (Action::RotateCw, vec![KEY_UP]),
+            (Action::RotateCcw, vec![',' as i32]),
+            (Action::Rotate180, vec!['.' as i32]),
+            (Action::Hold, vec!['/' as i32]),
+        ];
+        [left, right].map(|keys| Self {
+            keys: keys.iter().chain(common.iter()).cloned().collect(),
+        })
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            keys: vec![
+                (Action::MoveLeft, vec![KEY_LEFT]),
+                (Action::MoveRight, vec![KEY_RIGHT]),
+                (Action::SoftDrop, vec![KEY_DOWN]),
+                (Action::HardDrop, vec![32]),
+                (Action::RotateCw, vec![KEY_UP, 'x' as i32]),
+                (Action::RotateCcw, vec!['z' as i32]),
+                (Action::Rotate180, vec!['a' as i32]),
+                (Action::Hold, vec!['c' as i32]),
+                (Action::Pause, vec!['p' as i32]),
+                (Action::Restart, vec!['r' as i32]),
+                (Action::Save, vec!['s' as i32]),
+                (Action::Quit, vec!['q' as i32]),
+            ],
+        }
+    }
+}
+
+/// Parse a quoted string or an array of quoted strings, followed by
+/// an optional comment
+fn strings(value: &str) -> Option<Vec<String>> {
+    let mut chars = value.trim().chars().peekable();
+    let array = chars.peek() == Some(&'[');
+    if array {
+        chars.next();
+    }
+
+    let mut list = Vec::new();
+    loop {
+        match chars.next()? {
+            c if c.is_whitespace() || (array && c == ',') => continue,
+            ']' if array => break,
+            quote @ '"' | quote @ '\'' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next()? {
+                        '\\' if quote == '"' => s.push(chars.next()?),
+                        c if c == quote => break,
+                        c => s.push(c),
+                    }
+                }
+                list.push(s);
+                if !array {
+                    break;
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    // Only whitespace and a comment may follow
+    let rest: String = chars.collect();
+    let rest = rest.trim();
+    if rest.is_empty() || rest.starts_with('#') {
+        Some(list)
+    } else {
+        None
+    }
+}
+
+/// Parse a number of milliseconds, followed by an optional comment
+fn millis(value: &str) -> Option<Duration> {
+    let ms = value.split('#').next()?.trim().parse().ok()?;
+    Some(Duration::from_millis(ms))
+}
+
+/// Parse a boolean, followed by an optional comment
+fn boolean(value: &str) -> Option<bool> {
+    value.split('#').next()?.trim().parse().ok()
+}
+
+/// Parse a start level, followed by an optional comment
+fn level(value: &str) -> Option<i32> {
+    let level = value.split('#').next()?.trim().parse().ok()?;
+    Some(level).filter(|level| (1..=LEVEL_MAX).contains(level))
+}
+
+/// Quote a key name for the config file
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The settings of the config file
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The key bindings
+    pub keymap: Keymap,
+    /// The time that a piece on the ground waits before it locks
+    pub lock_delay: Duration,
+    /// The delay before a held key starts to shift the piece
+    pub das: Duration,
+    /// The time between two shifts of a held key
+    pub arr: Duration,
+    /// The duration of the line clear animation, zero turns it off
+    pub line_clear: Duration,
+    /// The level that a single player game starts at
+    pub level: i32,
+    /// Show where the piece would land
+    pub ghost: bool,
+    /// Draw the blocks with colors, if the terminal has them
+    pub colors: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keymap: Keymap::default(),
+            lock_delay: LOCK_DELAY,
+            das: DAS,
+            arr: ARR,
+            line_clear: LINE_CLEAR,
+            level: 1,
+            ghost: true,
+            colors: true,
+        }
+    }
+}
+
+impl Config {
+    /// Get the default location of the config file,
+    /// `$XDG_CONFIG_HOME/retris/config.toml` or `~/.config/retris/config.toml`
+    pub fn path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("retris").join("config.toml"))
+    }
+
+    /// Load the config file, a missing file keeps the defaults
+    pub fn load(path: Option<PathBuf>) -> Result<Self, String> {
+        let mut config = Self::default();
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(config),
+        };
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(config),
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+        };
+
+        let mut section = String::new();
+        for (n, line) in data.lines().enumerate() {
+            let error = |what: String| format!("{}:{}: {}", path.display(), n + 1, what);
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            } else if line.starts_with('[') {
+                let end = line
+                    .find(']')
+                    .ok_or_else(|| error("invalid section".into()))?;
+                section = line[1..end].trim().to_string();
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected name = value".into()))?;
+            let (name, value) = (name.trim(), value.trim());
+
+            match section.as_str() {
+                "keys" => {
+                    let action = name.parse().map_err(error)?;
+                    let keys = strings(value)
+                        .ok_or_else(|| error(format!("invalid keys: {}", value)))?
+                        .iter()
+                        .map(|k| key(k).ok_or_else(|| error(format!("unknown key: {}", k))))
+                        .collect::<Result<_, _>>()?;
+                    config.keymap.bind(action, keys);
+                }
+                "game" => {
+                    let ms = || {
+                        millis(value)
+                            .ok_or_else(|| error(format!("invalid milliseconds: {}", value)))
+                    };
+                    let flag = || {
+                        boolean(value).ok_or_else(|| error(format!("invalid boolean: {}", value)))
+                    };
+                    match name {
+                        "lock_delay" => config.lock_delay = ms()?,
+                        "das" => config.das = ms()?,
+                        "arr" => config.arr = ms()?,
+                        "line_clear" => config.line_clear = ms()?,
+                        "level" => {
+                            config.level = level(value)
+                                .ok_or_else(|| error(format!("invalid level: {}", value)))?
+                        }
+                        "ghost" => config.ghost = flag()?,
+                        "colors" => config.colors = flag()?,
+                        _ => return Err(error(format!("unknown setting: {}", name))),
+                    }
+                }
+                _ => return Err(error(format!("unknown setting: {}", name))),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Write all settings to the config file, replacing the old one
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut data = String::from("[keys]\n");
+        for (action, keys) in self.keymap.bindings() {
+            let names: Vec<String> = keys.iter().map(|k| quote(&key_name(*k))).collect();
+            let _ = match names.as_slice() {
+                [name] => writeln!(data, "{} = {}", action, name),
+                names => writeln!(data, "{} = [{}]", action, names.join(", ")),
+            };
+        }
+        let _ = write!(
+            data,
+            "\n[game]\nlock_delay = {}\ndas = {}\narr = {}\nline_clear = {}\n\
+             level = {}\nghost = {}\ncolors = {}\n",
+            self.lock_delay.as_millis(),
+            self.das.as_millis(),
+            self.arr.as_millis(),
+            self.line_clear.as_millis(),
+            self.level,
+            self.ghost,
+            self.colors
+        );
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /// Write a config file for a test into the temporary directory
+    fn temp(name: &str, data: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("retris-{}-{}.toml", process::id(), name));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    /// Load a config file from the data
+    fn load(name: &str, data: &str) -> Result<Config, String> {
+        let path = temp(name, data);
+        let config = Config::load(Some(path.clone()));
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn parses_strings() {
+        let list = |list: &[&str]| Some(list.iter().map(|s| s.to_string()).collect());
+        assert_eq!(strings(r#""a""#), list(&["a"]));
+        assert_eq!(strings("'a'"), list(&["a"]));
+        assert_eq!(strings(r#""\"" # quote"#), list(&["\""]));
+        assert_eq!(strings(r#"'\'"#), list(&["\\"]));
+        assert_eq!(strings(r#"["a", 'b' ,"c"] # keys"#), list(&["a", "b", "c"]));
+        assert_eq!(strings("[]"), list(&[]));
+        for invalid in ["", "a", r#""a"#, r#""a" b"#, r#"["a""#, r#""a", "b""#].iter() {
+            assert_eq!(strings(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn names_keys() {
+        assert_eq!(key("x"), Some('x' as i32));
+        assert_eq!(key("Space"), Some(32));
+        assert_eq!(key("left"), Some(KEY_LEFT));
+        assert_eq!(key("nokey"), None);
+        assert_eq!(key_name(KEY_PPAGE), "pageup");
+        assert_eq!(key_name('#' as i32), "#");
+        assert!(bindable('q' as i32));
+        assert!(!bindable(KEY_F(1)));
+    }
+
+    #[test]
+    fn rebinds_keys() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.action('x' as i32), Some(Action::RotateCw));
+
+        // A key can only be bound to one action
+        keymap.bind(Action::Hold, vec!['x' as i32, 'c' as i32]);
+        assert_eq!(keymap.action('x' as i32), Some(Action::Hold));
+        assert_eq!(keymap.action(KEY_UP), Some(Action::RotateCw));
+        assert_eq!(keymap.name(Action::Hold), "x");
+
+        keymap.bind(Action::RotateCw, vec![]);
+        assert_eq!(keymap.action(KEY_UP), None);
+        assert_eq!(keymap.name(Action::RotateCw), "-");
+    }
+
+    #[test]
+    fn loads_the_config() {
+        let config = load(
+            "load",
+            "# rETRIS\n\
+             [keys]\n\
+             hold = \"h\"  # comment\n\
+             rotate_cw = ['w', \"up\"]\n\
+             \n\
+             [ game ]\n\
+             das = 100 # ms\n\
+             level = 5\n\
+             ghost = false\n",
+        )
+        .unwrap();
+        assert_eq!(config.keymap.action('h' as i32), Some(Action::Hold));
+        assert_eq!(config.keymap.action('c' as i32), None);
+        assert_eq!(config.keymap.action('w' as i32), Some(Action::RotateCw));
+        assert_eq!(config.keymap.action('x' as i32), None);
+        assert_eq!(config.das, Duration::from_millis(100));
+        assert_eq!(config.arr, ARR);
+        assert_eq!(config.level, 5);
+        assert!(!config.ghost);
+        assert!(config.colors);
+
+        // The last binding of a key wins
+        let config = load("duplicate", "[keys]\nhold = 'x'\npause = 'x'\n").unwrap();
+        assert_eq!(config.keymap.action('x' as i32), Some(Action::Pause));
+        assert_eq!(config.keymap.name(Action::Hold), "-");
+
+        let missing = env::temp_dir().join(format!("retris-{}-missing", process::id()));
+        assert!(Config::load(Some(missing)).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        for (data, error) in [
+            ("[keys\n", ":1: invalid section"),
+            ("[keys]\nhold\n", ":2: expected name = value"),
+            ("[keys]\njump = 'x'\n", ":2: unknown action: jump"),
+            ("[keys]\nhold = x\n", ":2: invalid keys: x"),
+            ("[keys]\nhold = 'f1'\n", ":2: unknown key: f1"),
+            ("[game]\ndas = fast\n", ":2: invalid milliseconds: fast"),
+            ("[game]\nlevel = 21\n", ":2: invalid level: 21"),
+            ("[game]\nghost = 1\n", ":2: invalid boolean: 1"),
+            ("[game]\nspeed = 1\n", ":2: unknown setting: speed"),
+            ("[sound]\nbell = true\n", ":2: unknown setting: bell"),
+            ("ghost = true\n", ":1: unknown setting: ghost"),
+        ]
+        .iter()
+        {
+            let err = load("invalid", data).unwrap_err();
+            assert!(err.ends_with(error), "{}", err);
+        }
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let mut config = Config::default();
+        config
+            .keymap
+            .bind(Action::Hold, vec!['"' as i32, '\\' as i32, 32]);
+        config.lock_delay = Duration::from_millis(250);
+        config.level = 7;
+        config.colors = false;
+
+        let path = temp("save", "");
+        config.save(&path).unwrap();
+        let loaded = Config::load(Some(path.clone()));
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        let bindings: Vec<_> = config.keymap.bindings().collect();
+        assert_eq!(loaded.keymap.bindings().collect::<Vec<_>>(), bindings);
+        assert_eq!(loaded.lock_delay, config.lock_delay);
+        assert_eq!(loaded.level, 7);
+        assert!(!loaded.colors);
+    }
+}
@@ -0,0 +1,130 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::clock::minutes;
+use crate::record::{sgr, Cast};
+use retris::{GameState, Replay};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// The width of the status next to the field
+const STATUS_WIDTH: usize = 32;
+
+/// The foreground and background ANSI colors of the blocks by id, like
+/// the color pairs of a terminal with 256 colors
+const COLORS: [(i16, i16); 8] = [
+    (0, 6),
+    (0, 4),
+    (0, 208),
+    (0, 3),
+    (0, 2),
+    (0, 5),
+    (0, 1),
+    (7, 0),
+];
+
+/// Render a block with the letter of its piece
+fn pixel(name: char, id: u8) -> String {
+    let (fg, bg) = COLORS[(id as usize - 1) % COLORS.len()];
+    format!("{}{}\x1b[0m", sgr(fg, bg), name)
+}
+
+/// Render the field and the status of the game as a screen of ANSI
+/// escape sequences
+fn render(state: &GameState, time: Duration) -> String {
+    let (height, width) = (state.height(), state.width() as usize);
+    let piece = state.piece();
+    let falling: Vec<(i32, i32)> = match state.done() {
+        None => piece.pixels().collect(),
+        Some(_) => Vec::new(),
+    };
+    let next: String = state.queue().map(|piece| piece.name()).collect();
+    let mut status = vec![
+        "rETRIS".to_string(),
+        String::new(),
+        format!("Score: {}", state.score()),
+        format!("Level: {}", state.level()),
+        format!("Lines: {}", state.lines()),
+        format!("{}: {}", state.mode(), minutes(time)),
+        format!("Seed: {}", state.seed()),
+        String::new(),
+        format!("Next: {}", next),
+    ];
+    if state.rules().hold() {
+        status.push(format!(
+            "Hold: {}",
+            state.hold().map_or('-', |piece| piece.name())
+        ));
+    }
+    if let Some(reason) = state.done() {
+        status.push(String::new());
+        status.push(format!("GAME OVER: {}", reason));
+    }
+
+    let border = format!("+{}+", "-".repeat(width));
+    let mut lines = vec![border.clone()];
+    for y in 0..height {
+        let mut line = String::from("|");
+        for x in 0..width as i32 {
+            let id = if falling.contains(&(y, x)) {
+                piece.id()
+            } else {
+                state.cell(y, x)
+            };
+            if id == 0 {
+                line.push(' ');
+            } else {
+                line.push_str(&pixel(state.name(id), id));
+            }
+        }
+        line.push('|');
+        lines.push(line);
+    }
+    lines.push(border);
+
+    // The status can be taller than a small field
+    while lines.len() < status.len() {
+        lines.push(" ".repeat(width + 2));
+    }
+    for (line, status) in lines.iter_mut().zip(status) {
+        line.push_str("  ");
+        line.push_str(&status);
+    }
+
+    format!("\x1b[H\x1b[2J{}", lines.join("\r\n"))
+}
+
+/// Export a replay as an asciinema cast file without the terminal, the
+/// frames are rendered after every input at the time of the input
+pub fn export(replay: &Replay, path: &Path) -> io::Result<()> {
+    let mut state = GameState::with_size(replay.seed(), replay.height(), replay.width());
+    state.set_rules(replay.rules());
+    state.set_mode(replay.mode());
+    state.set_level(replay.level());
+
+    let mut cast = Cast::new(File::create(path)?);
+    let width = replay.width() as usize + 4 + STATUS_WIDTH;
+    cast.begin(width as i32, replay.height().max(12) + 2)?;
+    cast.output(Duration::default(), render(&state, Duration::default()))?;
+    for (time, input) in replay.events() {
+        state.apply(*input);
+        cast.output(*time, render(&state, *time))?;
+    }
+
+    cast.flush()
+}
@@ -0,0 +1,1563 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::piece::{Piece, Rotation, Tetromino, PIECE_WIDTH};
+use crate::puzzle::Goal;
+use crate::replay::Input;
+use crate::stats::Stats;
+use rand::{thread_rng, Rng};
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The default height of the field, following the guideline
+pub const GAME_HEIGHT: i32 = 20;
+/// The default width of the field, following the guideline
+pub const GAME_WIDTH: i32 = 10;
+/// The smallest height or width of the field that fits every piece
+pub const GAME_MIN: i32 = PIECE_WIDTH as i32;
+/// The largest height or width of the field that a terminal can show
+pub const GAME_MAX: i32 = 100;
+
+/// The id of the cells of garbage rows
+pub const GARBAGE: u8 = 8;
+
+/// The first line of a saved game
+const SAVE_HEADER: &str = "retris save 1";
+
+/// The most pieces that a saved game can have dealt, each of them is
+/// dealt again when the game is loaded
+const DEALT_MAX: usize = 1_000_000;
+
+/// The row where new pieces spawn, the flat pieces show their lower row
+/// at the top of the field
+const SPAWN_Y: i32 = -1;
+
+/// The number of upcoming pieces in the preview queue
+pub const QUEUE_SIZE: usize = 5;
+
+/// The default time that a piece on the ground waits before it locks
+pub const LOCK_DELAY: Duration = Duration::from_millis(500);
+/// The number of moves on the ground that reset the lock delay
+const LOCK_RESETS: usize = 15;
+
+/// The number of cleared lines that advance the level
+const LEVEL_LINES: usize = 10;
+/// The level where the speed curve stops getting faster, and the
+/// highest level that a game can start at
+pub const LEVEL_MAX: i32 = 20;
+
+/// Points for clearing one to four rows at once, multiplied by the level
+const CLEAR_POINTS: [i32; 5] = [0, 100, 300, 500, 800];
+/// Points of the classic rules for clearing one to four rows at once
+const CLASSIC_POINTS: [i32; 5] = [0, 40, 100, 300, 1200];
+/// Frames per row of the classic speed curve, by level
+const CLASSIC_FRAMES: [u32; 30] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+    1,
+];
+/// Points that a zen game loses when the stack reaches the top
+const ZEN_PENALTY: i32 = 500;
+/// The level where the speed curve of a zen game stops getting faster
+const ZEN_LEVEL: i32 = 10;
+/// Frames per second of the classic game
+const CLASSIC_FPS: f64 = 60.0988;
+/// Garbage rows for the opponent for clearing one to four rows at once
+const GARBAGE_ROWS: [usize; 5] = [0, 0, 1, 2, 4];
+
+/// The reason why the game ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameOver {
+    /// A piece was locked partially above the visible field
+    LockOut,
+    /// A new piece overlapped the stack when it spawned
+    BlockOut,
+    /// The player gave up
+    Quit,
+    /// The goal of the game mode was reached
+    Finished,
+    /// The time limit of the game mode ran out
+    TimeUp,
+    /// Garbage pushed the stack out of the field
+    TopOut,
+    /// The fixed sequence of pieces ran out
+    NoPieces,
+}
+
+impl fmt::Display for GameOver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameOver::LockOut => write!(f, "Lock out"),
+            GameOver::BlockOut => write!(f, "Block out"),
+            GameOver::Quit => write!(f, "Quit"),
+            GameOver::Finished => write!(f, "Finished"),
+            GameOver::TimeUp => write!(f, "Time up"),
+            GameOver::TopOut => write!(f, "Top out"),
+            GameOver::NoPieces => write!(f, "Out of pieces"),
+        }
+    }
+}
+
+/// The game modes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Mode {
+    /// The endless game
+    #[default]
+    Marathon,
+    /// Clear 40 lines as fast as possible
+    Sprint,
+    /// Score as many points as possible in two minutes
+    Ultra,
+    /// Play without a game over and at a limited speed
+    Zen,
+}
+
+impl Mode {
+    /// The number of lines that finish the game
+    pub fn goal(self) -> Option<usize> {
+        match self {
+            Mode::Sprint => Some(40),
+            _ => None,
+        }
+    }
+
+    /// The time limit of the game
+    pub fn time_limit(self) -> Option<Duration> {
+        match self {
+            Mode::Ultra => Some(Duration::from_secs(120)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Marathon => write!(f, "Marathon"),
+            Mode::Sprint => write!(f, "Sprint"),
+            Mode::Ultra => write!(f, "Ultra"),
+            Mode::Zen => write!(f, "Zen"),
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "marathon" => Ok(Mode::Marathon),
+            "sprint" => Ok(Mode::Sprint),
+            "ultra" => Ok(Mode::Ultra),
+            "zen" => Ok(Mode::Zen),
+            _ => Err(format!("unknown game mode: {}", s)),
+        }
+    }
+}
+
+/// The rules of the game
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Rules {
+    /// The rules of the modern games
+    #[default]
+    Guideline,
+    /// The rules of the classic NES game: no hold, no ghost piece, a
+    /// single preview, no hard drop, no kicks and no 180 degree
+    /// rotations, pieces lock as soon as they land, its speed curve,
+    /// scoring and randomizer
+    Classic,
+}
+
+impl Rules {
+    /// The number of upcoming pieces that are shown
+    pub fn previews(self) -> usize {
+        match self {
+            Rules::Guideline => QUEUE_SIZE,
+            Rules::Classic => 1,
+        }
+    }
+
+    /// Can a piece be swapped with the hold slot?
+    pub fn hold(self) -> bool {
+        self == Rules::Guideline
+    }
+
+    /// Is the ghost piece shown where the piece would land?
+    pub fn ghost(self) -> bool {
+        self == Rules::Guideline
+    }
+
+    /// Can a piece be dropped and locked at once?
+    pub fn hard_drop(self) -> bool {
+        self == Rules::Guideline
+    }
+
+    /// Is a rotated piece kicked away from the walls and the stack?
+    pub fn kicks(self) -> bool {
+        self == Rules::Guideline
+    }
+
+    /// Can a piece be rotated by 180 degrees?
+    pub fn half(self) -> bool {
+        self == Rules::Guideline
+    }
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rules::Guideline => write!(f, "Guideline"),
+            Rules::Classic => write!(f, "Classic"),
+        }
+    }
+}
+
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "guideline" => Ok(Rules::Guideline),
+            "classic" => Ok(Rules::Classic),
+            _ => Err(format!("unknown rules: {}", s)),
+        }
+    }
+}
+
+/// The state of a rETRIS game, without any user interface.
+#[derive(Debug, Clone)]
+pub struct GameState {
+    /// The tetromino pieces
+    tetromino: Tetromino,
+    /// The seed of the piece order
+    seed: u64,
+    /// The game mode
+    mode: Mode,
+    /// The rules of the game
+    rules: Rules,
+    /// The number of rows of the field
+    height: i32,
+    /// The number of columns of the field
+    width: i32,
+    /// The state of the field, 0 is empty or the id of a locked piece
+    data: Vec<u8>,
+    /// The falling piece
+    piece: Piece,
+    /// The upcoming pieces, the next one first
+    queue: VecDeque<Piece>,
+    /// The piece in the hold slot
+    hold: Option<Piece>,
+    /// Was the hold slot already used for the falling piece?
+    held: bool,
+    /// The time that a piece on the ground waits before it locks
+    lock_delay: Duration,
+    /// The number of moves of the falling piece on the ground
+    resets: usize,
+    /// The current score
+    score: i32,
+    /// The number of cleared lines
+    lines: usize,
+    /// The rows that were removed by the last locked piece
+    cleared: Vec<i32>,
+    /// The garbage rows that wait to rise, with the column of their hole
+    garbage: Vec<(usize, i32)>,
+    /// The garbage rows that the last locked piece sends to the opponent
+    sent: usize,
+    /// Game Over! And the reason for it
+    done: Option<GameOver>,
+    /// Is the game paused?
+    paused: bool,
+    /// The level, based on the cleared lines
+    level: i32,
+    /// The level that the game started at
+    start: i32,
+    /// The statistics of the game
+    stats: Stats,
+    /// The goal that finishes the game, besides the goal of the mode
+    goal: Option<Goal>,
+    /// Are the pieces a fixed sequence instead of random ones?
+    fixed: bool,
+    /// Was the last move of the falling piece a rotation?
+    spun: bool,
+    /// Did the bot play the game?
+    autoplay: bool,
+    /// Is the stack hidden from the player?
+    invisible: bool,
+}
+
+impl GameState {
+    /// Initialize a new game with a random order of pieces
+    pub fn new() -> Self {
+        Self::with_seed(thread_rng().gen())
+    }
+
+    /// Initialize a new game, the same seed deals the same pieces
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_size(seed, GAME_HEIGHT, GAME_WIDTH)
+    }
+
+    /// Initialize a new game on a field of the specified size,
+    /// which is between `GAME_MIN` and `GAME_MAX` rows and columns
+    pub fn with_size(seed: u64, height: i32, width: i32) -> Self {
+        let height = height.clamp(GAME_MIN, GAME_MAX);
+        let width = width.clamp(GAME_MIN, GAME_MAX);
+        let mut tetromino = Tetromino::new(seed);
+        let (piece, queue) = Self::first(&mut tetromino, width);
+
+        Self {
+            tetromino,
+            seed,
+            mode: Mode::default(),
+            rules: Rules::default(),
+            height,
+            width,
+            data: vec![0; height as usize * width as usize],
+            piece,
+            queue,
+            hold: None,
+            held: false,
+            lock_delay: LOCK_DELAY,
+            resets: 0,
+            score: 0,
+            lines: 0,
+            cleared: Vec::new(),
+            garbage: Vec::new(),
+            sent: 0,
+            done: None,
+            paused: false,
+            level: 1,
+            start: 1,
+            stats: Stats::default(),
+            goal: None,
+            fixed: false,
+            spun: false,
+            autoplay: false,
+            invisible: false,
+        }
+    }
+
+    /// Deal the first piece and fill the queue
+    fn first(tetromino: &mut Tetromino, width: i32) -> (Piece, VecDeque<Piece>) {
+        let mut piece = tetromino.next().unwrap_or_default();
+        let queue = (0..QUEUE_SIZE)
+            .map(|_| tetromino.next().unwrap_or_default())
+            .collect();
+        piece.setyx(SPAWN_Y, Self::spawn_x(width));
+        (piece, queue)
+    }
+
+    /// The seed of the piece order
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The game mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Set the game mode, before the game starts
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// The rules of the game
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Set the rules before the game starts, which deals the pieces
+    /// again with the randomizer of the rules
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+        self.tetromino = Self::randomizer(rules, self.seed);
+        let (piece, queue) = Self::first(&mut self.tetromino, self.width);
+        self.piece = piece;
+        self.queue = queue;
+    }
+
+    /// Create the randomizer of the rules that deals the pieces
+    fn randomizer(rules: Rules, seed: u64) -> Tetromino {
+        match rules {
+            Rules::Guideline => Tetromino::new(seed),
+            Rules::Classic => Tetromino::classic(seed),
+        }
+    }
+
+    /// The number of rows of the field
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// The number of columns of the field
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// The column where new pieces spawn, centered on the field: columns
+    /// 4 to 7 of the standard field
+    fn spawn_x(width: i32) -> i32 {
+        (width - PIECE_WIDTH as i32) / 2
+    }
+
+    /// The falling piece
+    pub fn piece(&self) -> &Piece {
+        &self.piece
+    }
+
+    /// The next piece, if any is left
+    pub fn next(&self) -> Option<&Piece> {
+        self.queue.front()
+    }
+
+    /// Deal a fixed sequence of pieces by their letters instead of random
+    /// ones, the game is over when they ran out.  Returns false if a
+    /// letter is not a piece.
+    pub fn set_sequence(&mut self, names: &[char]) -> bool {
+        let pieces: Option<VecDeque<Piece>> = names
+            .iter()
+            .map(|name| self.tetromino.get(self.id(*name)?).cloned())
+            .collect();
+        let mut pieces = match pieces {
+            Some(pieces) if !pieces.is_empty() => pieces,
+            _ => return false,
+        };
+        self.piece = pieces.pop_front().unwrap_or_default();
+        self.piece.setyx(SPAWN_Y, Self::spawn_x(self.width));
+        self.queue = pieces;
+        self.fixed = true;
+        true
+    }
+
+    /// The goal that finishes the game, besides the goal of the mode
+    pub fn goal(&self) -> Option<Goal> {
+        self.goal
+    }
+
+    /// Set a goal that finishes the game, before the game starts
+    pub fn set_goal(&mut self, goal: Option<Goal>) {
+        self.goal = goal;
+    }
+
+    /// Did the bot play the game, or a part of it before it was saved?
+    pub fn autoplay(&self) -> bool {
+        self.autoplay
+    }
+
+    /// Mark the game as played by the bot, which stays in a saved game
+    pub fn set_autoplay(&mut self) {
+        self.autoplay = true;
+    }
+
+    /// Is the stack hidden from the player?  The game still tracks it,
+    /// it is up to the user interface to hide it
+    pub fn invisible(&self) -> bool {
+        self.invisible
+    }
+
+    /// Hide the stack from the player, before the game starts
+    pub fn set_invisible(&mut self, invisible: bool) {
+        self.invisible = invisible;
+    }
+
+    /// The upcoming pieces that the rules show, the next one first
+    pub fn queue(&self) -> impl Iterator<Item = &Piece> {
+        self.queue.iter().take(self.rules.previews())
+    }
+
+    /// The piece in the hold slot
+    pub fn hold(&self) -> Option<&Piece> {
+        self.hold.as_ref()
+    }
+
+    /// The current score
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    /// The number of cleared lines
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// The rows that were removed by the last locked piece, numbered
+    /// as they were before the stack above them fell down
+    pub fn cleared(&self) -> &[i32] {
+        &self.cleared
+    }
+
+    /// The number of garbage rows that wait to rise from the bottom
+    pub fn pending(&self) -> usize {
+        self.garbage
+            .iter()
+            .fold(0, |pending: usize, (rows, _)| pending.saturating_add(*rows))
+    }
+
+    /// The number of garbage rows that the last locked piece sends to
+    /// the opponent, after it cancelled the pending garbage
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    /// The statistics of the game
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// The current level, going up from the start level every 10 lines
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    /// Start the game at a higher level, up to `LEVEL_MAX`, which makes
+    /// the pieces fall faster from the beginning
+    pub fn set_level(&mut self, level: i32) {
+        self.start = level.clamp(1, LEVEL_MAX);
+        self.level = self.start + (self.lines / LEVEL_LINES) as i32;
+    }
+
+    /// The time it takes the piece to fall by one row at the current level,
+    /// following the speed curve of the guideline or the classic game
+    pub fn gravity(&self) -> Duration {
+        let level = match self.mode {
+            Mode::Zen => self.level.min(ZEN_LEVEL),
+            _ => self.level,
+        };
+        match self.rules {
+            Rules::Guideline => {
+                let level = f64::from(level.min(LEVEL_MAX) - 1);
+                Duration::from_secs_f64((0.8 - level * 0.007).powf(level))
+            }
+            Rules::Classic => {
+                let level = (level as usize - 1).min(CLASSIC_FRAMES.len() - 1);
+                Duration::from_secs_f64(f64::from(CLASSIC_FRAMES[level]) / CLASSIC_FPS)
+            }
+        }
+    }
+
+    /// The time that a piece on the ground waits before it locks,
+    /// without any delay once the piece was moved too often or in
+    /// the classic rules
+    pub fn lock_delay(&self) -> Duration {
+        if self.resets >= LOCK_RESETS || self.rules == Rules::Classic {
+            Duration::default()
+        } else {
+            self.lock_delay
+        }
+    }
+
+    /// Set the time that a piece on the ground waits before it locks
+    pub fn set_lock_delay(&mut self, delay: Duration) {
+        self.lock_delay = delay;
+    }
+
+    /// The number of moves of the falling piece on the ground,
+    /// each of them resets the lock delay
+    pub fn resets(&self) -> usize {
+        self.resets
+    }
+
+    /// Is the falling piece on the ground, so it cannot fall any further?
+    pub fn grounded(&self) -> bool {
+        !self.fits(&self.piece, self.piece.y + 1, self.piece.x)
+    }
+
+    /// The reason why the game ended, if it is over
+    pub fn done(&self) -> Option<GameOver> {
+        self.done
+    }
+
+    /// Is the game paused?
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause or resume the game, returns true if it is paused now
+    pub fn pause(&mut self) -> bool {
+        self.paused = !self.paused && self.done.is_none();
+        self.paused
+    }
+
+    /// Is the game frozen because it is over or paused?
+    fn frozen(&self) -> bool {
+        self.done.is_some() || self.paused
+    }
+
+    /// Get the piece id of a locked cell, 0 if it is empty
+    pub fn cell(&self, y: i32, x: i32) -> u8 {
+        self.index(y, x).map_or(0, |idx| self.data[idx])
+    }
+
+    /// The locked cells of the field, row by row
+    pub fn cells(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Replace the locked cells of the field with a snapshot of the same
+    /// size, returns false if the size does not match
+    pub fn set_cells(&mut self, cells: &[u8]) -> bool {
+        if cells.len() != self.data.len() {
+            return false;
+        }
+        self.data.copy_from_slice(cells);
+        true
+    }
+
+    /// Get the piece id of a letter, or the id of garbage for '#'
+    pub fn id(&self, name: char) -> Option<u8> {
+        (1..=GARBAGE).find(|id| self.name(*id) == name)
+    }
+
+    /// Get the letter of a piece id
+    pub fn name(&self, id: u8) -> char {
+        if id == GARBAGE {
+            return '#';
+        }
+        self.tetromino.get(id).map_or(' ', Piece::name)
+    }
+
+    /// Get coordinates by index
+    pub fn getyx(&self, idx: usize) -> (i32, i32) {
+        (
+            (idx / self.width as usize) as i32,
+            (idx % self.width as usize) as i32,
+        )
+    }
+
+    /// Get index by coordinates
+    pub fn index(&self, y: i32, x: i32) -> Option<usize> {
+        if y < 0 || x < 0 || y >= self.height || x >= self.width {
+            return None;
+        }
+        Some((self.width * y + x) as usize)
+    }
+
+    /// Does the piece fit on the field at the specified coordinates?
+    pub fn fits(&self, piece: &Piece, y: i32, x: i32) -> bool {
+        piece
+            .pixels()
+            .map(|(py, px)| (py - piece.y + y, px - piece.x + x))
+            .all(|(py, px)| {
+                (0..self.width).contains(&px)
+                    && py < self.height
+                    && (py < 0 || self.cell(py, px) == 0)
+            })
+    }
+
+    /// Move the falling piece, if it fits
+    fn shift(&mut self, dy: i32, dx: i32) -> bool {
+        let (y, x) = (self.piece.y + dy, self.piece.x + dx);
+        if self.frozen() || !self.fits(&self.piece, y, x) {
+            return false;
+        }
+        if self.grounded() {
+            self.resets += 1;
+        }
+        self.piece.setyx(y, x);
+        self.spun = false;
+        true
+    }
+
+    /// Move the piece one column to the left
+    pub fn left(&mut self) -> bool {
+        self.shift(0, -1)
+    }
+
+    /// Move the piece one column to the right
+    pub fn right(&mut self) -> bool {
+        self.shift(0, 1)
+    }
+
+    /// Move the piece one row down, a soft drop scores one point per row
+    pub fn down(&mut self) -> bool {
+        let moved = self.shift(1, 0);
+        if moved {
+            self.score += 1;
+        }
+        moved
+    }
+
+    /// Move the piece towards the column of its leftmost pixel, if the way is free
+    pub fn column(&mut self, column: i32) {
+        let target = column - self.piece.left();
+        while self.piece.x != target && self.shift(0, (target - self.piece.x).signum()) {}
+    }
+
+    /// Rotate the piece, kicking it away from walls and the stack if
+    /// the rotated piece does not fit where it is
+    pub fn rotate(&mut self, rotation: Rotation) -> bool {
+        if self.frozen() || (rotation == Rotation::Half && !self.rules.half()) {
+            return false;
+        }
+
+        let kicks = if self.rules.kicks() {
+            self.piece.kicks(rotation)
+        } else {
+            vec![(0, 0)]
+        };
+        let mut piece = self.piece.clone();
+        piece.rotate(rotation);
+
+        for (dy, dx) in kicks {
+            let (y, x) = (piece.y + dy, piece.x + dx);
+            if self.fits(&piece, y, x) {
+                if self.grounded() {
+                    self.resets += 1;
+                }
+                piece.setyx(y, x);
+                self.piece = piece;
+                self.spun = true;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Get a copy of the falling piece at the row where it would land
+    pub fn ghost(&self) -> Piece {
+        let mut ghost = self.piece.clone();
+        while self.fits(&ghost, ghost.y + 1, ghost.x) {
+            ghost.y += 1;
+        }
+        ghost
+    }
+
+    /// Swap the falling piece with the hold slot, or with the next piece
+    /// if the slot is empty. This is only allowed once per piece.
+    pub fn swap(&mut self) -> bool {
+        if self.frozen() || self.held || !self.rules.hold() {
+            return false;
+        }
+        // A fixed sequence needs another piece to hold the first one
+        if self.hold.is_none() && self.queue.is_empty() {
+            return false;
+        }
+
+        // Put the piece back into its initial orientation
+        let held = self
+            .tetromino
+            .get(self.piece.id())
+            .cloned()
+            .unwrap_or_else(|| self.piece.clone());
+        self.piece = match self.hold.replace(held) {
+            Some(piece) => piece,
+            None => self.deal(),
+        };
+        self.piece.setyx(SPAWN_Y, Self::spawn_x(self.width));
+        self.held = true;
+        self.resets = 0;
+        self.spun = false;
+
+        if !self.fits(&self.piece, self.piece.y, self.piece.x) {
+            self.gameover(GameOver::BlockOut);
+        }
+
+        true
+    }
+
+    /// Jump to the last possible row and lock the piece there,
+    /// scoring two points per row. Returns the number of removed
+    /// rows like `step`.
+    pub fn hard_drop(&mut self) -> Option<usize> {
+        if !self.rules.hard_drop() {
+            return None;
+        }
+        let y = self.piece.y;
+        while self.shift(1, 0) {}
+        self.score += 2 * (self.piece.y - y);
+        self.lock()
+    }
+
+    /// Let the piece fall by one row, a piece on the ground stays there
+    /// until it is locked
+    pub fn step(&mut self) -> bool {
+        self.shift(1, 0)
+    }
+
+    /// Store the piece on the stack if it cannot fall any further,
+    /// returns the number of removed rows
+    pub fn lock(&mut self) -> Option<usize> {
+        if self.frozen() || !self.grounded() {
+            return None;
+        }
+
+        let lockout = self.piece.pixels().any(|(y, _)| y < 0);
+        let rows = self.store();
+
+        if lockout {
+            self.gameover(GameOver::LockOut);
+        } else if !self.fits(&self.piece, self.piece.y, self.piece.x) {
+            self.gameover(GameOver::BlockOut);
+        }
+
+        Some(rows)
+    }
+
+    /// Apply an input to the game, returns the number of removed rows
+    /// if a piece was locked
+    pub fn apply(&mut self, input: Input) -> Option<usize> {
+        if self.done.is_none() && !self.paused && input.moves() {
+            self.stats.input();
+        }
+        match input {
+            Input::Left => {
+                self.left();
+            }
+            Input::Right => {
+                self.right();
+            }
+            Input::Down => {
+                self.down();
+            }
+            Input::Rotate(rotation) => {
+                self.rotate(rotation);
+            }
+            Input::Hold => {
+                self.swap();
+            }
+            Input::Column(column) => self.column(column),
+            Input::HardDrop => return self.hard_drop(),
+            Input::Step => {
+                self.step();
+            }
+            Input::Lock => return self.lock(),
+            Input::Pause => {
+                self.pause();
+            }
+            Input::Quit => self.gameover(GameOver::Quit),
+            Input::TimeUp => self.gameover(GameOver::TimeUp),
+            Input::Garbage(rows, hole) => self.garbage(rows, hole),
+        }
+        None
+    }
+
+    /// End the game
+    pub fn gameover(&mut self, reason: GameOver) {
+        // A zen game goes on with a smaller stack instead of topping out
+        let topout = matches!(
+            reason,
+            GameOver::LockOut | GameOver::BlockOut | GameOver::TopOut
+        );
+        if self.mode == Mode::Zen && topout {
+            self.relieve();
+            return;
+        }
+        if self.done.is_none() {
+            self.done = Some(reason);
+        }
+        self.paused = false;
+    }
+
+    /// Remove the bottom half of the stack when it reached the top of a
+    /// zen game, or the whole stack if the piece still does not fit
+    fn relieve(&mut self) {
+        let half = (self.height / 2 * self.width) as usize;
+        let len = self.data.len();
+        self.data.copy_within(..len - half, half);
+        self.data[..half].fill(0);
+        if !self.fits(&self.piece, self.piece.y, self.piece.x) {
+            self.data.fill(0);
+        }
+        self.score = (self.score - ZEN_PENALTY).max(0);
+    }
+
+    /// Take the next piece from the queue and refill it from the bag
+    fn deal(&mut self) -> Piece {
+        if !self.fixed {
+            self.queue
+                .push_back(self.tetromino.next().unwrap_or_default());
+        }
+        self.queue.pop_front().unwrap_or_default()
+    }
+
+    /// Queue rows of garbage with a hole in the column, they rise from
+    /// the bottom when the next piece locks without clearing any rows.
+    /// Garbage without a hole in the field is ignored, its full rows
+    /// would be cleared for free.
+    pub fn garbage(&mut self, rows: usize, hole: i32) {
+        if self.done.is_none() && rows > 0 && (0..self.width).contains(&hole) {
+            self.garbage.push((rows.min(self.height as usize), hole));
+        }
+    }
+
+    /// Raise the stack by the pending garbage, the game is over if the
+    /// stack is pushed out of the field
+    fn raise(&mut self) {
+        let width = self.width as usize;
+        for (rows, hole) in std::mem::take(&mut self.garbage) {
+            let cells = rows.min(self.height as usize) * width;
+            if self.data[..cells].iter().any(|id| *id != 0) {
+                self.gameover(GameOver::TopOut);
+            }
+            self.data.drain(..cells);
+            let hole = hole as usize;
+            self.data
+                .extend((0..cells).map(|i| if i % width == hole { 0 } else { GARBAGE }));
+        }
+
+        // Push the falling piece up if the garbage overlaps it
+        while !self.fits(&self.piece, self.piece.y, self.piece.x) {
+            self.piece.y -= 1;
+        }
+    }
+
+    /// Did the T piece spin into its place?  Its last move was a rotation
+    /// and three of the four corners of its box are blocked.
+    fn tspin(&self) -> bool {
+        if self.piece.name() != 'T' || !self.spun {
+            return false;
+        }
+        let (y, x) = (self.piece.y, self.piece.x);
+        let blocked = |y: i32, x: i32| {
+            !(0..self.width).contains(&x) || y >= self.height || (y >= 0 && self.cell(y, x) != 0)
+        };
+        [(y, x), (y, x + 2), (y + 2, x), (y + 2, x + 2)]
+            .iter()
+            .filter(|(y, x)| blocked(*y, *x))
+            .count()
+            >= 3
+    }
+
+    /// Put the piece on the stack, spawn the next one and remove full rows
+    fn store(&mut self) -> usize {
+        let id = self.piece.id();
+        let tspin = self.tspin();
+        for (y, x) in self.piece.pixels() {
+            if let Some(idx) = self.index(y, x) {
+                self.data[idx] = self.piece.id();
+            }
+        }
+
+        self.piece = self.deal();
+        self.piece.setyx(SPAWN_Y, Self::spawn_x(self.width));
+        self.held = false;
+        self.resets = 0;
+        self.spun = false;
+
+        let rows = self.remove();
+        self.lines += rows;
+        self.stats.place(id, rows);
+        let goal = self
+            .goal
+            .is_some_and(|goal| goal.reached(self.lines, rows, tspin, &self.data));
+        if goal || self.mode.goal().is_some_and(|goal| self.lines >= goal) {
+            self.gameover(GameOver::Finished);
+        }
+        let points = match self.rules {
+            Rules::Guideline => CLEAR_POINTS,
+            Rules::Classic => CLASSIC_POINTS,
+        };
+        self.score += points[rows.min(4)] * self.level;
+        self.level = self.start + (self.lines / LEVEL_LINES) as i32;
+
+        // Cleared rows cancel the pending garbage before they attack
+        // the opponent, otherwise the garbage rises
+        let mut attack = GARBAGE_ROWS[rows.min(4)];
+        while attack > 0 && !self.garbage.is_empty() {
+            let cancel = attack.min(self.garbage[0].0);
+            self.garbage[0].0 -= cancel;
+            attack -= cancel;
+            if self.garbage[0].0 == 0 {
+                self.garbage.remove(0);
+            }
+        }
+        self.sent = attack;
+        if rows == 0 {
+            self.raise();
+        }
+
+        // A fixed sequence ends the game when it ran out of pieces
+        if self.fixed && self.piece.id() == 0 {
+            self.gameover(GameOver::NoPieces);
+        }
+
+        rows
+    }
+
+    /// Remove full rows and return their number
+    fn remove(&mut self) -> usize {
+        let width = self.width as usize;
+        let mut data = vec![0; self.data.len()];
+        let mut removed = 0;
+        let mut row = 1;
+
+        self.cleared.clear();
+        for (y, r) in self.data.chunks(width).enumerate().rev() {
+            if !r.contains(&0) {
+                self.cleared.push(y as i32);
+                removed += 1;
+            } else {
+                let j = data.len() - (row * width);
+                data[j..(j + width)].copy_from_slice(r);
+                row += 1;
+            }
+        }
+        self.data = data;
+
+        removed
+    }
+
+    /// Get a piece from its letter, rotation state and coordinates, as
+    /// they are written in a saved game
+    fn parse_piece(&self, value: &str) -> Option<Piece> {
+        let mut words = value.split_whitespace();
+        let name = words.next()?.parse().ok()?;
+        let rotation = words
+            .next()?
+            .parse()
+            .ok()
+            .filter(|rotation| *rotation < 4)?;
+        let (y, x) = (words.next()?.parse().ok()?, words.next()?.parse().ok()?);
+        // Kicks lift a piece at most a few rows above the field
+        if words.next().is_some() || !(-(PIECE_WIDTH as i32)..self.height).contains(&y) {
+            return None;
+        }
+
+        let mut piece = self.tetromino.get(self.id(name)?)?.clone();
+        while piece.rotation() != rotation {
+            piece.rotate(Rotation::Clockwise);
+        }
+        piece.setyx(y, x);
+        Some(piece)
+    }
+
+    /// Get the default location of the saved game,
+    /// `$XDG_DATA_HOME/retris/save` or `~/.local/share/retris/save`
+    pub fn save_path() -> Option<PathBuf> {
+        let data = env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+        Some(data.join("retris").join("save"))
+    }
+
+    /// Write the game and the time that it was played to a file, so it
+    /// can be resumed later.  The order of the following pieces is
+    /// restored from the seed and the number of pieces that were dealt.
+    pub fn save(&self, path: &Path, time: Duration) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut data = format!(
+            "{}\nseed {}\nmode {}\nrules {}\nsize {}x{}\nlevel {}\ntime {}\n\
+             score {}\nlines {}\nstats {}\ndealt {}\n",
+            SAVE_HEADER,
+            self.seed,
+            self.mode,
+            self.rules,
+            self.height,
+            self.width,
+            self.start,
+            time.as_millis(),
+            self.score,
+            self.lines,
+            self.stats,
+            self.tetromino.dealt()
+        );
+        let piece = &self.piece;
+        data.push_str(&format!(
+            "piece {} {} {} {}\n",
+            piece.name(),
+            piece.rotation(),
+            piece.y,
+            piece.x
+        ));
+        let queue: String = self.queue.iter().map(Piece::name).collect();
+        data.push_str(&format!("queue {}\n", queue));
+        if let Some(hold) = self.hold.as_ref() {
+            data.push_str(&format!("hold {}\n", hold.name()));
+        }
+        if self.held {
+            data.push_str("held\n");
+        }
+        if self.autoplay {
+            data.push_str("autoplay\n");
+        }
+        if self.invisible {
+            data.push_str("invisible\n");
+        }
+        for (rows, hole) in self.garbage.iter() {
+            data.push_str(&format!("garbage {} {}\n", rows, hole));
+        }
+
+        // The rows of the field from the top, like the board of a puzzle
+        data.push_str("field\n");
+        for row in self.data.chunks(self.width as usize) {
+            let row: String = row
+                .iter()
+                .map(|id| if *id == 0 { '.' } else { self.name(*id) })
+                .collect();
+            data.push_str(&row);
+            data.push('\n');
+        }
+
+        fs::write(path, data)
+    }
+
+    /// Load a saved game, returns the game and the time that it was
+    /// played before it was saved
+    pub fn load(path: &Path) -> io::Result<(Self, Duration)> {
+        let data = fs::read_to_string(path)?;
+        let invalid = |what: String| io::Error::new(ErrorKind::InvalidData, what);
+
+        let mut lines = data.lines();
+        if lines.next() != Some(SAVE_HEADER) {
+            return Err(invalid("not a saved game".to_string()));
+        }
+
+        // The settings are followed by the rows of the field
+        let settings: Vec<(&str, &str)> = lines
+            .by_ref()
+            .take_while(|line| *line != "field")
+            .map(|line| line.split_once(' ').unwrap_or((line, "")))
+            .collect();
+        let rows: Vec<&str> = lines.collect();
+
+        let size: String = setting(&settings, "size").map_err(invalid)?;
+        let (height, width) = size
+            .split_once('x')
+            .and_then(|(height, width)| Some((height.parse().ok()?, width.parse().ok()?)))
+            .filter(|(height, width)| {
+                (GAME_MIN..=GAME_MAX).contains(height) && (GAME_MIN..=GAME_MAX).contains(width)
+            })
+            .ok_or_else(|| invalid(format!("invalid size: {}", size)))?;
+        let seed = setting(&settings, "seed").map_err(invalid)?;
+        let mut state = Self::with_size(seed, height, width);
+        state.mode = setting(&settings, "mode").map_err(invalid)?;
+        state.rules = setting(&settings, "rules").map_err(invalid)?;
+        state.score = setting(&settings, "score").map_err(invalid)?;
+        state.lines = setting(&settings, "lines").map_err(invalid)?;
+        state.stats = setting(&settings, "stats").map_err(invalid)?;
+        state.set_level(setting(&settings, "level").map_err(invalid)?);
+        let time = Duration::from_millis(setting(&settings, "time").map_err(invalid)?);
+
+        // Deal the same number of pieces again to restore the randomizer
+        let dealt: usize = setting(&settings, "dealt").map_err(invalid)?;
+        if dealt > DEALT_MAX {
+            return Err(invalid(format!("invalid dealt: {}", dealt)));
+        }
+        state.tetromino = Self::randomizer(state.rules, seed);
+        state.tetromino.by_ref().take(dealt).for_each(drop);
+
+        let piece: String = setting(&settings, "piece").map_err(invalid)?;
+        state.piece = state
+            .parse_piece(&piece)
+            .ok_or_else(|| invalid(format!("invalid piece: {}", piece)))?;
+        let queue: String = setting(&settings, "queue").map_err(invalid)?;
+        state.queue = queue
+            .chars()
+            .map(|name| state.tetromino.get(state.id(name)?).cloned())
+            .collect::<Option<_>>()
+            .filter(|queue: &VecDeque<Piece>| !queue.is_empty())
+            .ok_or_else(|| invalid(format!("invalid queue: {}", queue)))?;
+
+        for (key, value) in settings.iter() {
+            match *key {
+                "hold" => {
+                    let hold = value
+                        .parse()
+                        .ok()
+                        .and_then(|name| state.tetromino.get(state.id(name)?).cloned());
+                    state.hold =
+                        Some(hold.ok_or_else(|| invalid(format!("invalid hold: {}", value)))?);
+                }
+                "held" => state.held = true,
+                "autoplay" => state.autoplay = true,
+                "invisible" => state.invisible = true,
+                "garbage" => {
+                    let garbage = value
+                        .split_once(' ')
+                        .and_then(|(rows, hole)| Some((rows.parse().ok()?, hole.parse().ok()?)))
+                        .filter(|(rows, hole)| {
+                            (1..=height as usize).contains(rows) && (0..width).contains(hole)
+                        });
+                    state.garbage.push(
+                        garbage.ok_or_else(|| invalid(format!("invalid garbage: {}", value)))?,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        if rows.len() != height as usize || rows.iter().any(|row| row.len() != width as usize) {
+            return Err(invalid("invalid field".to_string()));
+        }
+        let cells = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|c| if c == '.' { Some(0) } else { state.id(c) })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| invalid("invalid field".to_string()))?;
+        state.set_cells(&cells);
+
+        // The piece must be on the field and must not overlap the stack
+        if !state.fits(&state.piece, state.piece.y, state.piece.x) {
+            return Err(invalid(format!("invalid piece: {}", piece)));
+        }
+
+        Ok((state, time))
+    }
+}
+
+/// Parse the value of a setting of a saved game
+fn setting<T: FromStr>(settings: &[(&str, &str)], key: &str) -> Result<T, String> {
+    let value = settings
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| *value)
+        .ok_or_else(|| format!("missing {}", key))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid {}: {}", key, value))
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start a game on the standard field with a fixed sequence of pieces
+    /// and the rows of a stack at the bottom, '#' is a garbage cell
+    fn game(pieces: &str, stack: &[&str]) -> GameState {
+        let mut game = GameState::with_seed(0);
+        let names: Vec<char> = pieces.chars().collect();
+        assert!(game.set_sequence(&names));
+        let mut cells = vec![0; game.cells().len()];
+        let start = cells.len() - stack.len() * GAME_WIDTH as usize;
+        for (i, c) in stack.concat().chars().enumerate() {
+            cells[start + i] = if c == '#' { GARBAGE } else { 0 };
+        }
+        assert!(game.set_cells(&cells));
+        game
+    }
+
+    /// Get a row of the field as it is written in `game`
+    fn row(game: &GameState, y: i32) -> String {
+        (0..game.width())
+            .map(|x| match game.cell(y, x) {
+                0 => '.',
+                GARBAGE => '#',
+                id => game.name(id),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clears_a_line() {
+        let mut game = game("II", &["#.........", "###....###"]);
+        assert_eq!(game.hard_drop(), Some(1));
+        assert_eq!(game.lines(), 1);
+        assert_eq!(game.cleared(), [19]);
+        // The rows above the cleared row fall down
+        assert_eq!(row(&game, 19), "#.........");
+        assert_eq!(row(&game, 18), "..........");
+        assert_eq!(game.score(), 2 * 19 + 100);
+    }
+
+    #[test]
+    fn clears_four_rows() {
+        let mut game = game("II", &[".#########"; 4]);
+        assert!(game.rotate(Rotation::Clockwise));
+        game.column(0);
+        assert_eq!(game.hard_drop(), Some(4));
+        assert_eq!(game.cleared(), [19, 18, 17, 16]);
+        assert!(game.cells().iter().all(|id| *id == 0));
+    }
+
+    #[test]
+    fn scores_the_cleared_rows() {
+        for (rows, points) in [100, 300, 500, 800].iter().enumerate() {
+            for level in [1, 3].iter() {
+                let mut game = game("II", &vec![".#########"; rows + 1]);
+                game.set_level(*level);
+                assert!(game.rotate(Rotation::Clockwise));
+                game.column(0);
+                let drop = game.ghost().y - game.piece().y;
+                assert_eq!(game.hard_drop(), Some(rows + 1));
+                assert_eq!(game.score(), 2 * drop + points * level);
+            }
+        }
+
+        // The classic rules score one point per row of a soft drop
+        for (rows, points) in [40, 100, 300, 1200].iter().enumerate() {
+            let mut game = game("II", &vec![".#########"; rows + 1]);
+            game.set_rules(Rules::Classic);
+            assert!(game.rotate(Rotation::Clockwise));
+            game.column(0);
+            let drop = game.ghost().y - game.piece().y;
+            while game.down() {}
+            assert_eq!(game.apply(Input::Lock), Some(rows + 1));
+            assert_eq!(game.score(), drop + points);
+        }
+    }
+
+    #[test]
+    fn advances_the_level() {
+        let mut game = game("II", &["#.........", "###....###"]);
+        game.lines = 9;
+        assert_eq!(game.level(), 1);
+        assert_eq!(game.gravity(), Duration::from_secs(1));
+        assert_eq!(game.hard_drop(), Some(1));
+        assert_eq!(game.level(), 2);
+
+        // The cleared lines count up from the start level
+        game.set_level(5);
+        assert_eq!(game.level(), 6);
+    }
+
+    #[test]
+    fn speeds_up_with_the_level() {
+        let speeds = |rules, mode| {
+            let mut game = GameState::with_seed(0);
+            game.set_rules(rules);
+            game.set_mode(mode);
+            (1..=LEVEL_MAX + 10)
+                .map(|level| {
+                    game.level = level;
+                    game.gravity()
+                })
+                .collect::<Vec<Duration>>()
+        };
+
+        // Faster up to the highest level, and no faster after it
+        let guideline = speeds(Rules::Guideline, Mode::Marathon);
+        let max = LEVEL_MAX as usize - 1;
+        assert!(guideline[..=max].windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(guideline[max..]
+            .iter()
+            .all(|speed| *speed == guideline[max]));
+        assert_eq!(guideline[1].as_millis(), 793);
+
+        // The classic curve counts the frames of the original game
+        let classic = speeds(Rules::Classic, Mode::Marathon);
+        assert_eq!(classic[0].as_millis(), 798);
+        assert!(classic.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(classic[29], classic[0] / 48);
+
+        // A zen game stops getting faster at its own level
+        let zen = speeds(Rules::Guideline, Mode::Zen);
+        let max = ZEN_LEVEL as usize - 1;
+        assert!(zen[max..].iter().all(|speed| *speed == guideline[max]));
+    }
+
+    #[test]
+    fn rotates_through_states() {
+        let mut game = game("T", &[]);
+        assert!(game.rotate(Rotation::Clockwise));
+        assert_eq!(game.piece().rotation(), 1);
+        assert!(game.rotate(Rotation::CounterClockwise));
+        assert_eq!(game.piece().rotation(), 0);
+        assert!(game.rotate(Rotation::Half));
+        assert_eq!(game.piece().rotation(), 2);
+        assert!(game.rotate(Rotation::CounterClockwise));
+        assert_eq!(game.piece().rotation(), 1);
+        assert_eq!((game.piece().y, game.piece().x), (SPAWN_Y, 3));
+    }
+
+    #[test]
+    fn kicks_off_the_stack() {
+        // The T piece cannot turn in place and takes the first kick
+        // of its table, one column to the left
+        let mut game = game("T", &[]);
+        let mut cells = game.cells().to_vec();
+        cells[GAME_WIDTH as usize + 4] = GARBAGE;
+        assert!(game.set_cells(&cells));
+        assert!(game.rotate(Rotation::Clockwise));
+        assert_eq!((game.piece().y, game.piece().x), (SPAWN_Y, 2));
+    }
+
+    #[test]
+    fn kicks_off_the_wall() {
+        // The upright T piece at the left wall turns back to its spawn
+        // state one column to the right
+        let mut game = game("T", &[]);
+        assert!(game.rotate(Rotation::Clockwise));
+        game.column(0);
+        assert_eq!(game.piece().x, -1);
+        assert!(game.rotate(Rotation::CounterClockwise));
+        assert_eq!(game.piece().rotation(), 0);
+        assert_eq!((game.piece().y, game.piece().x), (SPAWN_Y, 0));
+    }
+
+    #[test]
+    fn kicks_off_the_floor() {
+        // The flat I piece on the floor stands up two rows higher, with
+        // the last kick of its table
+        let mut game = game("I", &[]);
+        while game.down() {}
+        assert_eq!((game.piece().y, game.piece().x), (18, 3));
+        assert!(game.rotate(Rotation::Clockwise));
+        assert_eq!((game.piece().y, game.piece().x), (16, 4));
+        assert!(game.piece().pixels().all(|(y, x)| x == 6 && y >= 16));
+    }
+
+    #[test]
+    fn plays_by_the_classic_rules() {
+        let mut game = GameState::with_seed(0);
+        game.set_rules(Rules::Classic);
+        assert!(game.set_sequence(&['T', 'T']));
+        assert_eq!(game.lock_delay(), Duration::default());
+        assert_eq!(game.hard_drop(), None);
+        assert!(!game.rotate(Rotation::Half));
+
+        // The T piece at the left wall cannot turn without a kick
+        assert!(game.rotate(Rotation::Clockwise));
+        game.column(0);
+        assert!(!game.rotate(Rotation::CounterClockwise));
+        assert_eq!(game.piece().rotation(), 1);
+    }
+
+    #[test]
+    fn raises_garbage() {
+        let mut game = game("OO", &[]);
+        game.garbage(2, 3);
+        assert_eq!(game.pending(), 2);
+        assert_eq!(game.hard_drop(), Some(0));
+        assert_eq!(game.pending(), 0);
+        assert_eq!(row(&game, 19), "###.######");
+        assert_eq!(row(&game, 18), "###.######");
+        // The stack was pushed up by the garbage
+        assert_eq!(row(&game, 17), "....OO....");
+        assert_eq!(row(&game, 16), "....OO....");
+        assert_eq!(game.done(), None);
+    }
+
+    #[test]
+    fn cancels_garbage() {
+        let mut game = game("II", &[".#########"; 4]);
+        game.garbage(3, 0);
+        assert!(game.rotate(Rotation::Clockwise));
+        game.column(0);
+        assert_eq!(game.hard_drop(), Some(4));
+        assert_eq!(game.pending(), 0);
+        assert_eq!(game.sent(), 1);
+    }
+
+    #[test]
+    fn garbage_tops_out() {
+        let mut game = game("OO", &[".........#"; GAME_HEIGHT as usize]);
+        game.garbage(1, 0);
+        assert_eq!(game.hard_drop(), Some(0));
+        assert_eq!(game.done(), Some(GameOver::TopOut));
+    }
+
+    /// Get a file for a test in the temporary directory
+    fn temp(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("retris-{}-{}", std::process::id(), name))
+    }
+
+    /// The letters of the falling piece, the queue and the hold slot
+    fn pieces(game: &GameState) -> String {
+        let piece = game.piece();
+        format!(
+            "{} {} {} {} {} {}",
+            piece.name(),
+            piece.rotation(),
+            piece.y,
+            piece.x,
+            game.queue.iter().map(Piece::name).collect::<String>(),
+            game.hold().map_or(' ', Piece::name)
+        )
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let mut game = GameState::with_seed(5);
+        game.set_mode(Mode::Sprint);
+        for input in [
+            Input::HardDrop,
+            Input::Hold,
+            Input::Left,
+            Input::HardDrop,
+            Input::Garbage(2, 1),
+            Input::Rotate(Rotation::Clockwise),
+            Input::Down,
+        ]
+        .iter()
+        {
+            game.apply(*input);
+        }
+
+        game.set_autoplay();
+        game.set_invisible(true);
+
+        let path = temp("save");
+        game.save(&path, Duration::from_millis(12345)).unwrap();
+        let loaded = GameState::load(&path);
+        fs::remove_file(&path).unwrap();
+        let (mut loaded, time) = loaded.unwrap();
+
+        assert_eq!(time, Duration::from_millis(12345));
+        assert_eq!(loaded.mode(), Mode::Sprint);
+        assert!(loaded.autoplay());
+        assert!(loaded.invisible());
+        assert_eq!(loaded.score(), game.score());
+        assert_eq!(loaded.pending(), 2);
+        assert_eq!(loaded.cells(), game.cells());
+        assert_eq!(pieces(&loaded), pieces(&game));
+
+        // The randomizer deals the same pieces after loading
+        for _ in 0..10 {
+            game.hard_drop();
+            loaded.hard_drop();
+        }
+        assert_eq!(loaded.cells(), game.cells());
+        assert_eq!(pieces(&loaded), pieces(&game));
+    }
+
+    #[test]
+    fn rejects_invalid_saves() {
+        let game = GameState::with_seed(5);
+        let path = temp("invalid");
+        game.save(&path, Duration::default()).unwrap();
+        let data = fs::read_to_string(&path).unwrap();
+        let piece = format!("piece {} 0 {} 3\n", game.piece().name(), SPAWN_Y);
+        assert!(data.contains(&piece));
+
+        for (from, to) in [
+            ("dealt 6\n", "dealt 18446744073709551615\n"),
+            (piece.as_str(), "piece T 0 -1 9\n"),
+            (piece.as_str(), "piece T 0 19 3\n"),
+            (piece.as_str(), "piece T 0 -1000 3\n"),
+            ("field\n", "garbage 2 10\nfield\n"),
+            ("field\n", "garbage 21 0\nfield\n"),
+        ]
+        .iter()
+        {
+            assert!(data.contains(from));
+            fs::write(&path, data.replace(from, to)).unwrap();
+            assert!(GameState::load(&path).is_err(), "{}", to);
+        }
+
+        // A piece that overlaps the stack
+        let data = data.replacen("..........\n", "....#.....\n", 1);
+        fs::write(&path, data).unwrap();
+        assert!(GameState::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
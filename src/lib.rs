@@ -0,0 +1,41 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+//! The rETRIS game engine.
+//!
+//! The engine keeps the state of a game without any user interface, so
+//! it can be driven by the curses frontend, a bot, or a test.
+
+extern crate rand;
+
+mod bot;
+mod game;
+mod piece;
+mod puzzle;
+mod replay;
+mod scores;
+mod stats;
+
+pub use bot::Bot;
+pub use game::{
+    GameOver, GameState, Mode, Rules, GAME_HEIGHT, GAME_MAX, GAME_MIN, GAME_WIDTH, GARBAGE,
+    LEVEL_MAX, LOCK_DELAY, QUEUE_SIZE,
+};
+pub use piece::{Piece, Rotation, Tetromino, PIECE_SIZE, PIECE_WIDTH};
+pub use puzzle::{Goal, Puzzle};
+pub use replay::{Input, Replay};
+pub use scores::{Score, Scores, SCORES_MAX};
+pub use stats::{Stats, PIECE_TYPES};
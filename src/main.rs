@@ -15,194 +15,515 @@
 //
 
 extern crate ncurses;
-extern crate rand;
-
+extern crate retris;
+
+mod bell;
+mod botio;
+mod clock;
+mod config;
+mod export;
+mod menu;
+mod net;
+mod record;
+mod shift;
+mod term;
+mod versus;
+
+use bell::{Bell, Event};
+use botio::bot_io;
+use clock::{minutes, Clock};
+use config::{Action, Config, Keymap};
+use export::export;
+use menu::{main_menu, Start};
 use ncurses::*;
-use rand::seq::SliceRandom;
-use rand::thread_rng;
-use std::convert::TryInto;
+use net::Connection;
+use rand::{thread_rng, Rng};
+use record::{Broadcast, Cast, FrameDump, Recorder, ReplayFile};
+use retris::{
+    Bot, GameOver, GameState, Input, Mode, Piece, Puzzle, Replay, Rotation, Rules, Score, Scores,
+    GAME_HEIGHT, GAME_MAX, GAME_MIN, GAME_WIDTH, GARBAGE, PIECE_TYPES, SCORES_MAX,
+};
+use shift::AutoShift;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::mem;
 use std::ops::Deref;
-
-const GAME_HEIGHT: i32 = 20;
-const GAME_WIDTH: i32 = 12;
-const GAME_FIELD: usize = (GAME_HEIGHT * GAME_WIDTH) as usize;
-
-const BLOCK_WIDTH: usize = 4;
-const BLOCK_SIZE: usize = BLOCK_WIDTH * BLOCK_WIDTH;
+use std::process;
+use std::time::{Duration, Instant};
+use term::{diagnose, Style, QUEUE_WIDTH, STATUS_HEIGHT};
+use versus::versus;
 
 const KEY_SPACE: i32 = 32;
+const KEY_ZERO: i32 = 48;
+const KEY_NINE: i32 = 57;
 const KEY_QUIT: i32 = 113;
-const KEY_RESTART: i32 = 114;
+const KEY_BACKSPACE_ASCII: i32 = 127;
+const KEY_NEWLINE: i32 = 10;
+const KEY_RETURN: i32 = 13;
+const KEY_ESCAPE: i32 = 27;
+
+/// The time to wait for input in every frame, in milliseconds
+const FRAME_TIME: i32 = 10;
+
+/// The time between two inputs of the bot
+const BOT_DELAY: Duration = Duration::from_millis(100);
+
+/// The time that locked cells stay visible in an invisible game
+const FADE_TIME: Duration = Duration::from_secs(2);
+/// The time that the stack is shown after a line clear in an invisible game
+const REVEAL_TIME: Duration = Duration::from_millis(500);
 
-/// The rETRIS game.
+/// The default duration of the line clear animation
+pub const LINE_CLEAR: Duration = Duration::from_millis(150);
+
+/// The orange of the L piece in terminals with 256 colors
+const COLOR_ORANGE: i16 = 208;
+
+/// The curses frontend of a rETRIS game.
 struct Game {
     /// The window representing the main playing field of the game
     field: WINDOW,
     /// The window of the game status and help
     status: WINDOW,
-    /// The state of the field
-    data: [u32; GAME_FIELD],
-    /// The current score
-    score: i32,
-    /// Game Over!
-    done: bool,
-    /// The level (based on max. height of rows)
-    level: i32,
+    /// The window of the upcoming pieces
+    queue: WINDOW,
+    /// The number of rows of the field
+    height: i32,
+    /// The number of columns of the field
+    width: i32,
+    /// How the game is rendered
+    style: Style,
+    /// The key bindings, for the help
+    keymap: Keymap,
+    /// Can the digits move the piece to a column?
+    columns: bool,
+    /// Show the key bindings in the status window?
+    help: bool,
+    /// Show where the piece would land?
+    ghost: bool,
+    /// Hide the locked cells after a while?
+    invisible: bool,
+    /// The time of the game when each cell of the field was locked
+    locked: Vec<Option<Duration>>,
+    /// The number of cleared lines at the last refresh
+    lines: usize,
+    /// Show the whole stack until this time of the game
+    reveal: Duration,
+    /// The time of the game
+    time: Duration,
+    /// Can the game be saved to resume it later?
+    save: bool,
 }
 
 impl Game {
-    /// Initialize a new game
-    pub fn new() -> Self {
+    /// Initialize the windows of a new game with a field of the
+    /// specified size in the center of the screen
+    pub fn new(style: Style, keymap: &Keymap, height: i32, width: i32) -> Self {
+        let xoff = getmaxx(curscr()) / 2 - ((width + 2) / 2);
+        Self::with_layout(style, keymap, height, width, 1, xoff)
+    }
+
+    /// Initialize the windows of a new game with the status window
+    /// starting at the left column and the field at column `xoff`
+    pub fn with_layout(
+        style: Style,
+        keymap: &Keymap,
+        height: i32,
+        width: i32,
+        left: i32,
+        xoff: i32,
+    ) -> Self {
         let yoff = 1;
-        let xoff = getmaxx(curscr()) / 2 - ((GAME_WIDTH + 2) / 2);
-        let level = 10;
 
-        let field = newwin(GAME_HEIGHT + 2, GAME_WIDTH + 2, yoff, xoff);
-        let status = newwin(GAME_HEIGHT + 2, xoff - 2, yoff, 1);
-        box_(field, 0, 0);
+        let field = newwin(height + 2, width + 2, yoff, xoff);
+        let status = newwin((height + 2).max(STATUS_HEIGHT), xoff - 1 - left, yoff, left);
+        let queue = newwin(height + 2, QUEUE_WIDTH, yoff, xoff + width + 4);
 
         keypad(field, true);
         intrflush(field, false);
-        halfdelay(level);
+        cbreak();
+        wtimeout(field, FRAME_TIME);
 
-        let mut game = Self {
+        Self {
             field,
             status,
-            data: [0 as u32; GAME_FIELD],
-            score: 0,
-            done: false,
-            level,
+            queue,
+            height,
+            width,
+            style,
+            keymap: keymap.clone(),
+            columns: true,
+            help: true,
+            ghost: true,
+            invisible: false,
+            locked: vec![None; (height * width) as usize],
+            lines: 0,
+            reveal: Duration::default(),
+            time: Duration::default(),
+            save: false,
+        }
+    }
+
+    /// Draw a piece, or its ghost, on the window with its matrix at the
+    /// specified coordinates
+    fn draw(&self, window: WINDOW, piece: &Piece, y: i32, x: i32, ghost: bool) {
+        let ch = if ghost {
+            self.style.ghost()
+        } else {
+            self.style.pixel(piece.name(), piece.id())
         };
-        game.refresh();
-        game
+        for (py, px) in piece.pixels() {
+            let (py, px) = (py - piece.y + y, px - piece.x + x);
+            if py > 0 {
+                mvwaddch(window, py, px, ch);
+            }
+        }
     }
 
     /// Update the game field
-    pub fn refresh(&mut self) {
-        let mut data: [u32; GAME_FIELD] = [0 as u32; GAME_FIELD];
-        let mut redraw = false;
-        let mut row = 1;
-
-        // Remove full rows
-        for r in self.data.chunks(GAME_WIDTH as usize).rev() {
-            if !r.contains(&0) {
-                redraw = true;
-            } else {
-                let j = GAME_FIELD - (row * GAME_WIDTH as usize);
-                data[j..(j + GAME_WIDTH as usize)].copy_from_slice(r);
-                row += 1;
-            }
+    pub fn refresh(&mut self, state: &GameState) {
+        werase(**self);
+        if state.paused() {
+            // Hide the field to prevent planning while paused
+            self.style.border(**self);
+            wrefresh(**self);
+            self.paused();
+            return;
         }
 
-        if redraw {
-            self.data = data;
-            wclear(**self);
+        // Remember when the cells were locked, a line clear shows the
+        // whole stack briefly and hides it again with all of its cells
+        for (locked, id) in self.locked.iter_mut().zip(state.cells()) {
+            *locked = match (*id, *locked) {
+                (0, _) => None,
+                (_, None) => Some(self.time),
+                (_, time) => time,
+            };
+        }
+        if state.lines() != self.lines {
+            self.lines = state.lines();
+            self.reveal = self.time + REVEAL_TIME;
+            for time in self.locked.iter_mut().flatten() {
+                *time = Duration::default();
+            }
+        }
+        let reveal = !self.invisible || state.done().is_some() || self.time < self.reveal;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let id = state.cell(y, x);
+                let locked = self.locked[(y * self.width + x) as usize];
+                let shown = reveal || locked.is_some_and(|time| self.time < time + FADE_TIME);
+                if id != 0 && shown {
+                    let ch = self.style.pixel(state.name(id), id);
+                    mvwaddch(**self, y + 1, x + 1, ch);
+                }
+            }
         }
-        for (i, ch) in self.data.iter().enumerate().filter(|(_, ch)| **ch != 0) {
-            let (y, x) = Self::getyx(i);
-            mvwaddch(**self, y as i32 + 1, x as i32 + 1, (*ch).into());
+        if state.done().is_none() {
+            if self.ghost && state.rules().ghost() {
+                let ghost = state.ghost();
+                self.draw(**self, &ghost, ghost.y + 1, ghost.x + 1, true);
+            }
+            let piece = state.piece();
+            self.draw(**self, piece, piece.y + 1, piece.x + 1, false);
         }
 
-        self.speed();
+        self.style.border(**self);
 
-        box_(**self, 0, 0);
+        // The meter of pending garbage rises on the left border
+        for y in 0..(state.pending() as i32).min(self.height) {
+            mvwaddch(**self, self.height - y, 0, self.style.meter());
+        }
         wrefresh(**self);
     }
 
-    /// Update the level and the game speed accordingly
-    fn speed(&mut self) {
-        let mut count = 0;
-
-        for (i, ch) in self.data.iter().enumerate() {
-            count = i;
-            if *ch != 0 {
-                break;
-            }
+    /// Animate the cleared rows before the stack falls down, sweeping
+    /// them away from the center outward
+    pub fn sweep(&mut self, state: &GameState, duration: Duration, recorder: &mut Recorder) {
+        let rows = state.cleared();
+        if rows.is_empty() || duration.as_millis() == 0 {
+            return;
         }
 
-        // Calculate level as a percentage of the utilized rows
-        let level = (((count as f32) / GAME_FIELD as f32) * 10.0) as i32;
+        let steps = (self.width + 1) / 2;
+        for step in 0..=steps {
+            werase(**self);
+            for y in 0..self.height {
+                if rows.contains(&y) {
+                    for x in 0..self.width {
+                        if (2 * x + 1 - self.width).abs() >= 2 * step {
+                            mvwaddch(**self, y + 1, x + 1, self.style.flash());
+                        }
+                    }
+                    continue;
+                }
 
-        // Only bump the level, never lower it
-        if level < self.level {
-            self.level = level;
-            halfdelay(self.level);
+                // Draw the stack where it was before the rows were removed
+                let row = y + rows.iter().filter(|row| **row > y).count() as i32;
+                for x in 0..self.width {
+                    let id = state.cell(row, x);
+                    if id != 0 {
+                        let ch = self.style.pixel(state.name(id), id);
+                        mvwaddch(**self, y + 1, x + 1, ch);
+                    }
+                }
+            }
+            self.style.border(**self);
+            wrefresh(**self);
+            recorder.frame();
+            napms((duration / (steps as u32 + 1)).as_millis() as i32);
         }
     }
 
-    /// Increase the score
-    pub fn addscore(&mut self, score: i32) {
-        self.score += score;
-    }
+    /// Draw the pause overlay over the middle of the field
+    fn paused(&self) {
+        let (height, width) = if self.save { (6, 12) } else { (5, 12) };
+        let y = getbegy(**self) + (self.height - height) / 2 + 1;
+        let x = getbegx(**self) + (self.width - width) / 2 + 1;
+        let window = newwin(height, width, y, x);
+
+        mvwaddstr(window, 1, (width - 6) / 2, "PAUSED");
+        mvwaddstr(window, 3, 1, "p: resume");
+        if self.save {
+            let save = format!("{}: save", self.keymap.name(Action::Save));
+            mvwaddstr(window, 4, 1, &save);
+        }
 
-    /// End the game
-    pub fn gameover(&mut self) {
-        self.done = true;
+        self.style.border(window);
+        wrefresh(window);
+        delwin(window);
     }
 
     /// Update the game status window
-    pub fn status(&mut self, block: &mut Block) {
-        wclear(self.status);
+    pub fn status(&mut self, state: &GameState) {
+        werase(self.status);
         mvwaddstr(self.status, 0, 0, "rETRIS");
         mvwaddstr(self.status, 1, 0, "(reyk's TETRIS)");
-        mvwaddstr(self.status, 3, 0, "Next block:");
-        block.setyx(BLOCK_WIDTH as i32, BLOCK_WIDTH as i32);
-        block.draw(self.status);
-        mvwaddstr(self.status, 9, 0, &format!("Score: {}", self.score));
-        mvwaddstr(self.status, 10, 0, &format!("Level: {}", 10 - self.level));
-        if self.done {
-            mvwaddstr(self.status, 12, 0, "GAME OVER!");
+        if state.rules().hold() {
+            mvwaddstr(self.status, 3, 0, "Hold:");
         }
+        if let Some(piece) = state.hold() {
+            self.draw(self.status, piece, 4, 4, false);
+        }
+        mvwaddstr(self.status, 9, 0, &format!("Score: {}", state.score()));
+        mvwaddstr(self.status, 10, 0, &format!("Level: {}", state.level()));
+        let lines = match state.mode().goal() {
+            Some(goal) => format!("Lines: {}/{}", state.lines(), goal),
+            None => format!("Lines: {}", state.lines()),
+        };
+        mvwaddstr(self.status, 11, 0, &lines);
+        let time = match state.mode().time_limit() {
+            Some(limit) => format!("{} left", minutes(limit.saturating_sub(self.time))),
+            None => minutes(self.time),
+        };
+        mvwaddstr(self.status, 12, 0, &format!("{}: {}", state.mode(), time));
+        let seed = match state.goal() {
+            Some(goal) => goal.to_string(),
+            None => format!("Seed: {}", state.seed()),
+        };
+        mvwaddstr(self.status, 13, 0, &seed);
+
+        // The speed of the player next to the score and level
+        let stats = state.stats();
         mvwaddstr(
             self.status,
-            getmaxy(self.status) - 3,
-            0,
-            "left / right/ down: move",
-        );
-        mvwaddstr(
-            self.status,
-            getmaxy(self.status) - 2,
-            0,
-            "up: rotate   space: drop",
-        );
-        mvwaddstr(
-            self.status,
-            getmaxy(self.status) - 1,
-            0,
-            "r: restart       q: quit",
+            9,
+            14,
+            &format!("PPS: {:.2}", stats.pps(self.time)),
         );
+        mvwaddstr(self.status, 10, 14, &format!("KPP: {:.2}", stats.kpp()));
+
+        let summary = match state.done() {
+            Some(GameOver::Finished) => format!("FINISHED in {}", minutes(self.time)),
+            Some(GameOver::TimeUp) => format!("TIME UP! {} points", state.score()),
+            Some(reason) => format!("GAME OVER! ({})", reason),
+            None => String::new(),
+        };
+        mvwaddstr(self.status, 14, 0, &summary);
+        let key = |action| self.keymap.name(action);
+        let columns = if self.columns {
+            "1-9 / 0: go to column"
+        } else {
+            ""
+        };
+        let help = [
+            columns.to_string(),
+            format!(
+                "{:<17}{}: pause",
+                format!("{}: hold", key(Action::Hold)),
+                key(Action::Pause)
+            ),
+            format!(
+                "{} / {}/ {}: move",
+                key(Action::MoveLeft),
+                key(Action::MoveRight),
+                key(Action::SoftDrop)
+            ),
+            format!(
+                "{:<13}{}: drop",
+                format!("{}: rotate", key(Action::RotateCw)),
+                key(Action::HardDrop)
+            ),
+            format!(
+                "{:<17}{}: 180",
+                format!("{}: rotate ccw", key(Action::RotateCcw)),
+                key(Action::Rotate180)
+            ),
+            format!(
+                "{:<17}{}: quit",
+                format!("{}: restart", key(Action::Restart)),
+                key(Action::Quit)
+            ),
+        ];
+        let maxy = getmaxy(self.status);
+        if self.help {
+            for (i, line) in help.iter().enumerate() {
+                mvwaddstr(self.status, maxy - help.len() as i32 + i as i32, 0, line);
+            }
+        }
         wrefresh(self.status);
     }
 
-    /// Put a block on the game field stack
-    pub fn store(&mut self, block: Block) {
-        self.addscore(10 - self.level);
-        block.store(**self, &mut self.data);
+    /// Update the window of the upcoming pieces, stacked from the top
+    pub fn queue(&mut self, state: &GameState) {
+        werase(self.queue);
+        mvwaddstr(self.queue, 0, 0, "Next:");
+        let fits = (getmaxy(self.queue) - 1) / 3;
+        for (i, piece) in state.queue().take(fits as usize).enumerate() {
+            self.draw(self.queue, piece, 1 + i as i32 * 3, 2, false);
+        }
+        wrefresh(self.queue);
     }
 
-    /// Get coordinates by relative index
-    pub fn getyx(idx: usize) -> (usize, usize) {
-        (idx / GAME_WIDTH as usize, idx % GAME_WIDTH as usize)
+    /// Render the field and the status at the time of the game
+    pub fn render(&mut self, state: &GameState, time: Duration) {
+        self.time = time;
+        self.refresh(state);
+        self.status(state);
+        self.queue(state);
     }
 
-    /// Get relative index by coordinates
-    pub fn index(y: i32, x: i32) -> i32 {
-        if y < 1 || x < 1 || y > GAME_HEIGHT + 1 || x > GAME_WIDTH + 1 {
-            return -1;
+    /// Show the statistics of the finished game over the middle of the
+    /// screen, with a note below them
+    pub fn summary(&self, state: &GameState, note: &str) {
+        let stats = state.stats();
+        let (height, width) = (14, 38);
+        let window = newwin(height, width, 4, getmaxx(curscr()) / 2 - width / 2);
+
+        mvwaddstr(window, 1, width / 2 - 6, "GAME SUMMARY");
+        let left = [
+            format!("Time     {:>8}", minutes(self.time)),
+            format!("Score    {:>8}", state.score()),
+            format!("Lines    {:>8}", state.lines()),
+            format!("Level    {:>8}", state.level()),
+            format!("Singles  {:>8}", stats.clears(1)),
+            format!("Doubles  {:>8}", stats.clears(2)),
+            format!("Triples  {:>8}", stats.clears(3)),
+            format!("Tetrises {:>8}", stats.clears(4)),
+        ];
+        let mut right = vec![
+            format!("Pieces {:>7}", stats.pieces()),
+            format!("PPS    {:>7.2}", stats.pps(self.time)),
+            format!("Keys   {:>7}", stats.inputs()),
+            format!("KPP    {:>7.2}", stats.kpp()),
+        ];
+        let pieces: Vec<String> = (1..=PIECE_TYPES as u8)
+            .map(|id| format!("{} {:>3}", state.name(id), stats.piece(id)))
+            .collect();
+        right.extend(pieces.chunks(2).map(|pair| pair.join("   ")));
+        for (i, line) in left.iter().enumerate() {
+            mvwaddstr(window, i as i32 + 3, 2, line);
+        }
+        for (i, line) in right.iter().enumerate() {
+            mvwaddstr(window, i as i32 + 3, 22, line);
         }
-        GAME_WIDTH * (y - 1) + (x - 1)
+        mvwaddstr(window, height - 2, 2, note);
+
+        self.style.border(window);
+        wrefresh(window);
+        delwin(window);
     }
 
-    /// Does a block pixel "fit" on the specified coordinate - is it empty?
-    pub fn fits(&self, y: i32, x: i32) -> bool {
-        let idx = Self::index(y, x);
-        if idx < 0 || self.data[idx as usize] != 0 {
-            return false;
+    /// Show a note below the game status
+    pub fn note(&mut self, note: &str) {
+        mvwaddstr(self.status, 15, 0, note);
+        wrefresh(self.status);
+    }
+
+    /// Ask for the initials of the player, returns None if skipped
+    pub fn initials(&mut self, recorder: &mut Recorder) -> Option<String> {
+        let mut name = String::new();
+
+        loop {
+            mvwaddstr(self.status, 14, 0, &format!("{:<24}", "NEW HIGH SCORE!"));
+            mvwaddstr(self.status, 15, 0, &format!("Initials: {:_<3}", name));
+            wrefresh(self.status);
+            recorder.frame();
+
+            match wgetch(**self) {
+                KEY_NEWLINE | KEY_RETURN | KEY_ENTER if !name.is_empty() => return Some(name),
+                KEY_ESCAPE => return None,
+                KEY_BACKSPACE | KEY_BACKSPACE_ASCII => {
+                    name.pop();
+                }
+                key @ 0..=127 if name.len() < 3 => {
+                    let c = key as u8 as char;
+                    if c.is_ascii_alphanumeric() {
+                        name.push(c.to_ascii_uppercase());
+                    }
+                }
+                _ => {}
+            }
         }
-        true
     }
 }
 
+/// Show the high score table over the middle of the screen, highlighting
+/// the new entry
+fn leaderboard(style: Style, scores: &Scores, rank: Option<usize>, note: &str) {
+    let (height, width) = (SCORES_MAX as i32 + 6, 38);
+    let window = newwin(height, width, 4, getmaxx(curscr()) / 2 - width / 2);
+
+    mvwaddstr(window, 1, width / 2 - 6, "HIGH SCORES");
+    mvwaddstr(
+        window,
+        2,
+        2,
+        &format!(
+            "{:>2}  {:<3} {:>6} {:>5} {:>2} {:<10}",
+            "", "Who", "Score", "Lines", "Lv", "Date"
+        ),
+    );
+    for (i, score) in scores.iter().enumerate() {
+        if rank == Some(i) {
+            wattron(window, A_REVERSE());
+        }
+        mvwaddstr(
+            window,
+            i as i32 + 3,
+            2,
+            &format!(
+                "{:>2}. {:<3} {:>6} {:>5} {:>2} {}",
+                i + 1,
+                score.name,
+                score.score,
+                score.lines,
+                score.level,
+                score.date()
+            ),
+        );
+        wattroff(window, A_REVERSE());
+    }
+    mvwaddstr(window, height - 2, 2, note);
+
+    style.border(window);
+    wrefresh(window);
+    delwin(window);
+}
+
 impl Deref for Game {
     type Target = WINDOW;
 
@@ -215,344 +536,784 @@ impl Drop for Game {
     fn drop(&mut self) {
         delwin(self.field);
         delwin(self.status);
+        delwin(self.queue);
     }
 }
 
-/// A tetromino block
-#[derive(Debug, Clone)]
-struct Block {
-    /// The 4x4 tetromino block
-    data: [u8; BLOCK_SIZE],
-    /// The type of the tetromino block
-    index: usize,
-    /// The current y location
-    y: i32,
-    /// The current x location
-    x: i32,
-    /// The individual id of the tetromino block
-    id: i16,
+/// The options of a new game from the command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Setup {
+    /// The game mode
+    mode: Mode,
+    /// The seed of the piece order, a random one for every game if unset
+    seed: Option<u64>,
+    /// The number of rows of the field
+    height: i32,
+    /// The number of columns of the field
+    width: i32,
+    /// The rules of a single player game
+    rules: Rules,
+    /// Hide the stack of a single player game
+    invisible: bool,
+    /// Let the bot play a single player game
+    ai: bool,
+    /// Resume the saved game instead of showing the main menu
+    resume: bool,
 }
 
-impl Block {
-    /// Return a new "empty" block
-    pub fn new() -> Self {
+impl Default for Setup {
+    fn default() -> Self {
         Self {
-            data: b"................".to_owned(),
-            index: 0,
-            x: 0,
-            y: 0,
-            id: 0,
+            mode: Mode::default(),
+            seed: None,
+            height: GAME_HEIGHT,
+            width: GAME_WIDTH,
+            rules: Rules::default(),
+            invisible: false,
+            ai: false,
+            resume: false,
         }
     }
+}
 
-    /// Set the individual block id
-    pub fn setid(&mut self, id: i16) {
-        self.id = id;
+/// Get the input of a key binding that moves the piece
+fn movement(action: Action) -> Option<Input> {
+    match action {
+        Action::HardDrop => Some(Input::HardDrop),
+        Action::RotateCw => Some(Input::Rotate(Rotation::Clockwise)),
+        Action::RotateCcw => Some(Input::Rotate(Rotation::CounterClockwise)),
+        Action::Rotate180 => Some(Input::Rotate(Rotation::Half)),
+        Action::Hold => Some(Input::Hold),
+        Action::SoftDrop => Some(Input::Down),
+        Action::MoveLeft => Some(Input::Left),
+        Action::MoveRight => Some(Input::Right),
+        _ => None,
     }
+}
 
-    /// Set the next row of the block to turn it into a tetromino
-    pub fn row(&mut self, row: &str) {
-        let i = self.index;
-        if i >= BLOCK_WIDTH || row.len() != BLOCK_WIDTH {
-            return;
+/// The timers that move the falling piece without any input
+#[derive(Debug, Default)]
+struct Timers {
+    /// The time of the last gravity step
+    tick: Duration,
+    /// The time when the piece landed on the ground
+    lock: Option<Duration>,
+}
+
+impl Timers {
+    /// Let gravity pull the piece down, lock it after the lock delay and
+    /// end the game at the time limit.  `resets` and `rows` are the moves
+    /// on the ground before the last input and the rows that it cleared,
+    /// every input is passed to `record`.  Returns true if gravity moved
+    /// the piece.
+    fn update(
+        &mut self,
+        state: &mut GameState,
+        now: Duration,
+        resets: usize,
+        rows: &mut Option<usize>,
+        record: &mut dyn FnMut(Input),
+    ) -> bool {
+        // Gravity ticks at the speed of the level, independent of the input;
+        // at high levels the piece can fall by several rows per frame
+        let mut gravity = false;
+        while !state.paused() && rows.is_none() && now - self.tick >= state.gravity() {
+            self.tick += state.gravity();
+            gravity = true;
+            record(Input::Step);
+            if !state.step() {
+                self.tick = now;
+                break;
+            }
         }
-        self.data[(i * BLOCK_WIDTH)..(i * BLOCK_WIDTH + BLOCK_WIDTH)]
-            .copy_from_slice(row.as_bytes());
-        self.index = i + 1;
-    }
 
-    /// Store the coordinates of the block
-    pub fn setyx(&mut self, y: i32, x: i32) {
-        self.y = y;
-        self.x = x;
-    }
+        // A piece on the ground locks after the lock delay, moving it
+        // on the ground restarts the delay
+        if !state.paused() {
+            if state.done().is_none() && state.grounded() && rows.is_none() {
+                if state.resets() != resets {
+                    self.lock = None;
+                }
+                let since = *self.lock.get_or_insert(now);
+                if now - since >= state.lock_delay() {
+                    record(Input::Lock);
+                    *rows = state.apply(Input::Lock);
+                    self.lock = None;
+                }
+            } else {
+                self.lock = None;
+            }
+        }
 
-    /// Get the coordinates of the block
-    pub fn getyx(idx: usize) -> (usize, usize) {
-        (idx / BLOCK_WIDTH, idx % BLOCK_WIDTH)
-    }
+        // The time limit of the game mode
+        let limit = state.mode().time_limit();
+        if state.done().is_none() && limit.is_some_and(|limit| now >= limit) {
+            record(Input::TimeUp);
+            state.apply(Input::TimeUp);
+        }
 
-    /// Rotate the block on the game field
-    pub fn rotate(&mut self, game: &Game) {
-        let mut new: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+        gravity
+    }
+}
 
-        // clear block
-        self.clear(**game);
+/// The bot that plays instead of the player, one input at a time
+struct Autoplay {
+    /// The bot that plans the moves
+    bot: Bot,
+    /// The inputs that are left to place the falling piece
+    plan: VecDeque<Input>,
+    /// The number of locked pieces when the plan was made
+    pieces: usize,
+    /// The time of the last input
+    last: Duration,
+}
 
-        // rotate each pixel by 90 degrees cw
-        for (i, c) in self.data.iter().enumerate() {
-            let (y, x) = Self::getyx(i);
-            let idx = BLOCK_WIDTH * (BLOCK_WIDTH - 1) + y - x * BLOCK_WIDTH;
-            // for ccw:
-            //let idx = BLOCK_WIDTH - 1 - y + x * BLOCK_WIDTH;
-            new[idx] = *c;
+impl Autoplay {
+    /// Start the bot
+    fn new() -> Self {
+        Self {
+            bot: Bot::default(),
+            plan: VecDeque::new(),
+            pieces: 0,
+            last: Duration::default(),
         }
+    }
 
-        let old = self.data;
-        self.data = new;
+    /// Get the next input of the bot at the time of the game, if it is
+    /// time for one
+    fn input(&mut self, state: &GameState, now: Duration) -> Option<Input> {
+        if state.done().is_some() || state.paused() || now < self.last + BOT_DELAY {
+            return None;
+        }
 
-        if !self.fits(&game, self.y, self.x) {
-            // revert to previous
-            self.data = old;
-            return;
+        // Plan again when the piece was locked before the plan was done
+        if self.plan.is_empty() || self.pieces != state.stats().pieces() {
+            self.plan = self.bot.plan(state).into();
+            self.pieces = state.stats().pieces();
         }
+        self.last = now;
+        self.plan.pop_front()
     }
+}
 
-    /// Draw the block on the specified window
-    pub fn draw(&self, window: WINDOW) {
-        self.fill(window, false, &mut []);
-    }
+/// Load the saved game, it is removed once the game has started
+fn resume() -> io::Result<(GameState, Duration)> {
+    let path = GameState::save_path().ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+    GameState::load(&path)
+}
 
-    /// Clear the block from the specified window
-    pub fn clear(&self, window: WINDOW) {
-        self.fill(window, true, &mut []);
+/// Play a new game, a puzzle or a resumed game, returns true if the
+/// player wants to restart
+fn play(
+    style: Style,
+    config: &Config,
+    setup: Setup,
+    start: &mut Start,
+    scores: &mut Scores,
+    recorder: &mut Recorder,
+    bell: &mut Bell,
+) -> bool {
+    let keymap = &config.keymap;
+
+    // A resumed game goes on at the time when it was saved, restarting
+    // it starts a new game
+    let saved = match mem::replace(start, Start::Game) {
+        Start::Resume(state, time) => Some((*state, time)),
+        other => {
+            *start = other;
+            None
+        }
+    };
+    let puzzle = match &*start {
+        Start::Puzzle(puzzle) => Some(puzzle),
+        _ => None,
+    };
+    let resumed = saved.is_some();
+    let demo = matches!(start, Start::Demo);
+    let mut autoplay = (setup.ai || demo).then(Autoplay::new);
+    let (mut state, time) = saved.unwrap_or_else(|| {
+        let seed = setup.seed.unwrap_or_else(|| thread_rng().gen());
+        let mut state = match puzzle {
+            Some(puzzle) => puzzle.start(),
+            None => GameState::with_size(seed, setup.height, setup.width),
+        };
+        // A puzzle is always an endless game with its own goal
+        if puzzle.is_none() {
+            state.set_rules(setup.rules);
+            state.set_mode(setup.mode);
+            state.set_invisible(setup.invisible);
+        }
+        state.set_level(config.level);
+        (state, Duration::default())
+    });
+
+    let mut game = Game::new(style, keymap, state.height(), state.width());
+    let mut clock = Clock::at(time);
+    let mut timers = Timers {
+        tick: time,
+        ..Timers::default()
+    };
+    let mut shown = 0;
+    let mut shift = AutoShift::new(config.das, config.arr);
+    let mut note = match (demo, autoplay.is_some()) {
+        (true, _) => "DEMO: press any key".to_string(),
+        (false, true) => "AUTOPLAY".to_string(),
+        (false, false) => String::new(),
+    };
+    game.ghost = config.ghost;
+    game.invisible = state.invisible();
+    game.save = puzzle.is_none() && !demo;
+    state.set_lock_delay(config.lock_delay);
+    if autoplay.is_some() {
+        state.set_autoplay();
     }
 
-    /// Draw the block on the specified window and save its pixels
-    pub fn store(&self, window: WINDOW, data: &mut [u32]) {
-        self.fill(window, false, data);
+    // The field of a puzzle or a resumed game is not in the replay, and
+    // a demo game keeps the last game of the player
+    if puzzle.is_some() || resumed || demo {
+        recorder.skip();
+    } else {
+        recorder.begin(&state);
+    }
+    game.render(&state, clock.now());
+    recorder.frame();
+
+    // The saved game can only be resumed once, it is removed when it
+    // is on the screen again
+    if resumed {
+        if let Some(path) = GameState::save_path() {
+            if let Err(err) = fs::remove_file(path) {
+                note = format!("Save not removed: {}", err);
+            }
+        }
     }
 
-    fn fill(&self, window: WINDOW, clear: bool, data: &mut [u32]) {
-        let mut py = self.y;
-        let mut px = self.x;
+    loop {
+        let mut rows = None;
+        let resets = state.resets();
 
-        for v in self.data.iter() {
-            let c = *v as char;
-            if px >= self.x + BLOCK_WIDTH as i32 {
-                px = self.x;
-                py += 1;
-            }
-            if py > 0 && c != '.' {
-                let mut ch: u32 = c.into();
-                if clear {
-                    ch = ' '.into();
-                } else if has_colors() {
-                    ch = (ACS_BLOCK() | COLOR_PAIR(self.id)).try_into().unwrap();
+        // Handle input, wait at most one frame for it.  Any key ends a
+        // demo game and goes back to the menu.
+        let key = wgetch(*game);
+        if demo && key != ERR {
+            return false;
+        }
+        let input = match keymap.action(key) {
+            Some(action @ Action::Save) | Some(action @ Action::Quit) if game.save => {
+                // Leave the game once it is saved, it can be resumed later.
+                // Quitting ends the game if it cannot be saved.
+                let path =
+                    GameState::save_path().ok_or_else(|| io::Error::from(ErrorKind::NotFound));
+                match path.and_then(|path| state.save(&path, clock.now())) {
+                    Ok(()) => {
+                        recorder.end();
+                        return false;
+                    }
+                    Err(err) => note = format!("Not saved: {}", err),
                 }
-                mvwaddch(window, py, px, ch.into());
-
-                let idx = Game::index(py, px);
-                if idx > 0 && data.len() >= idx as usize {
-                    data[idx as usize] = ch;
+                Some(Input::Quit).filter(|_| action == Action::Quit)
+            }
+            Some(Action::Quit) => Some(Input::Quit),
+            Some(Action::Pause) => {
+                // Stop the time and resume it where it was stopped
+                if state.paused() {
+                    clock.resume();
+                } else {
+                    clock.pause();
                 }
+                Some(Input::Pause)
             }
-            px += 1;
+            _ if state.paused() => None,
+            Some(Action::Restart) => {
+                recorder.end();
+                return true;
+            }
+            _ if autoplay.is_some() => None,
+            Some(action) => movement(action),
+            None if (KEY_ZERO..=KEY_NINE).contains(&key) => {
+                // Move the block over the selected column, if the way is free
+                let column = if key == KEY_ZERO { 10 } else { key - KEY_ZERO };
+                Some(Input::Column(column - 1))
+            }
+            None => None,
+        };
+
+        // Auto shift a held left or right key, any other key releases it,
+        // or let the bot move the piece instead of the player
+        let input = match autoplay.as_mut() {
+            Some(autoplay) => input.or_else(|| autoplay.input(&state, clock.now())),
+            None => shift.input(Instant::now(), input, key != ERR),
+        };
+        if let Some(input) = input {
+            recorder.input(clock.now(), input);
+            rows = state.apply(input);
+        }
+
+        let now = clock.now();
+        let mut record = |input| recorder.input(now, input);
+        let gravity = timers.update(&mut state, now, resets, &mut rows, &mut record);
+
+        // Animate the cleared rows while the time of the game stands still
+        if rows.is_some_and(|rows| rows > 0) {
+            clock.pause();
+            game.sweep(&state, config.line_clear, recorder);
+            clock.resume();
         }
-    }
 
-    /// Does the block fit on the game field?
-    pub fn fits(&self, game: &Game, y: i32, x: i32) -> bool {
-        let mut py = y;
-        let mut px = x;
+        match rows {
+            None => {}
+            Some(0) => bell.push(Event::Lock),
+            Some(1..=3) => bell.push(Event::Clear),
+            Some(_) => bell.push(Event::Tetris),
+        }
 
-        for v in self.data.iter() {
-            let c = *v as char;
-            if px >= x + BLOCK_WIDTH as i32 {
-                px = x;
-                py += 1;
+        // Render output if anything happened, or every tenth of a second
+        // to update the time
+        let changed = shown != now.as_millis() / 100;
+        shown = now.as_millis() / 100;
+        if key != ERR || input.is_some() || gravity || rows.is_some() || changed {
+            game.render(&state, now);
+            if !note.is_empty() {
+                game.note(&note);
             }
-            if c != '.'
-                && (px < 1 || px > GAME_WIDTH || py > GAME_HEIGHT || (py > 0 && !game.fits(py, px)))
-            {
+            recorder.frame();
+        }
+
+        if state.done().is_some() {
+            recorder.end();
+            bell.push(Event::GameOver);
+            bell.ring();
+            if demo {
                 return false;
             }
-            px += 1;
+
+            // Add the score to the high score table, only the endless
+            // game on the standard field is ranked by the score, unless
+            // the bot played it, even before it was saved, or the stack
+            // was hidden
+            let ranked = state.mode() == Mode::Marathon
+                && !state.autoplay()
+                && !state.invisible()
+                && state.rules() == Rules::Guideline
+                && state.goal().is_none()
+                && (state.height(), state.width()) == (GAME_HEIGHT, GAME_WIDTH);
+
+            // Show the summary of the game first, the high score table
+            // follows after a key
+            if ranked {
+                game.summary(&state, "Press any key");
+                recorder.frame();
+                while wgetch(*game) == ERR {}
+            } else {
+                let result = match (state.goal(), state.done()) {
+                    (Some(_), Some(GameOver::Finished)) => "SOLVED!  ",
+                    (Some(_), _) => "FAILED!  ",
+                    (None, _) => "",
+                };
+                let help = format!(
+                    "{}{}: restart  {}: quit",
+                    result,
+                    keymap.name(Action::Restart),
+                    keymap.name(Action::Quit)
+                );
+                game.summary(&state, &help);
+                recorder.frame();
+            }
+
+            let mut rank = None;
+            let mut note = String::new();
+            if ranked && scores.qualifies(state.score()) {
+                if let Some(name) = game.initials(recorder) {
+                    let score = Score::new(&name, state.score(), state.lines(), state.level());
+                    rank = scores.insert(score);
+                    if let Err(err) = scores.save() {
+                        note = format!("Not saved: {}", err);
+                    }
+                }
+                game.status(&state);
+            }
+            if ranked {
+                leaderboard(style, scores, rank, &note);
+                recorder.frame();
+            }
+
+            loop {
+                match keymap.action(wgetch(*game)) {
+                    Some(Action::Quit) => return false,
+                    Some(Action::Restart) => return true,
+                    _ => {}
+                }
+            }
         }
-        true
+
+        bell.ring();
     }
 }
 
-/// All tetromino blocks
-#[derive(Debug)]
-struct Tetromino {
-    /// A vector of all tetrominos (I, J, L, O, S, T, Z)
-    data: Vec<Block>,
+/// Play back a recorded game, faster or slower by the speed factor
+fn playback(style: Style, config: &Config, replay: &Replay, speed: f64, recorder: &mut Recorder) {
+    let keymap = &config.keymap;
+    let mut state = GameState::with_size(replay.seed(), replay.height(), replay.width());
+    let mut game = Game::new(style, keymap, state.height(), state.width());
+    game.ghost = config.ghost;
+    state.set_rules(replay.rules());
+    state.set_mode(replay.mode());
+    state.set_level(replay.level());
+    let mut clock = Clock::new();
+    let quit = format!("REPLAY  {}: quit", keymap.name(Action::Quit));
+    game.render(&state, Duration::default());
+    game.note(&quit);
+    recorder.frame();
+
+    for (time, input) in replay.events() {
+        // Wait for the time of the input, the replay can be stopped
+        while clock.now() < time.div_f64(speed) {
+            if keymap.action(wgetch(*game)) == Some(Action::Quit) {
+                return;
+            }
+        }
+        // The clock of the game stood still while the rows were cleared
+        if state.apply(*input).is_some_and(|rows| rows > 0) {
+            clock.pause();
+            game.sweep(&state, config.line_clear.div_f64(speed), recorder);
+            clock.resume();
+        }
+        game.render(&state, *time);
+        game.note(&quit);
+        recorder.frame();
+    }
+
+    game.note(&format!(
+        "END OF REPLAY  {}: quit",
+        keymap.name(Action::Quit)
+    ));
+    recorder.frame();
+    while keymap.action(wgetch(*game)) != Some(Action::Quit) {}
 }
 
-impl Tetromino {
-    /// Create the tetrominos
-    pub fn new() -> Self {
-        let mut data = Vec::new();
-        let mut block;
-
-        // I
-        block = Block::new();
-        block.setid(1);
-        block.row("..I.");
-        block.row("..I.");
-        block.row("..I.");
-        block.row("..I.");
-        data.push(block);
-
-        // J
-        block = Block::new();
-        block.setid(2);
-        block.row("..J.");
-        block.row("..J.");
-        block.row(".JJ.");
-        block.row("....");
-        data.push(block);
-
-        // L
-        block = Block::new();
-        block.setid(3);
-        block.row(".L..");
-        block.row(".L..");
-        block.row(".LL.");
-        block.row("....");
-        data.push(block);
-
-        // O
-        block = Block::new();
-        block.setid(4);
-        block.row("....");
-        block.row(".OO.");
-        block.row(".OO.");
-        block.row("....");
-        data.push(block);
-
-        // S
-        block = Block::new();
-        block.setid(5);
-        block.row(".S..");
-        block.row(".SS.");
-        block.row("..S.");
-        block.row("....");
-        data.push(block);
-
-        // T
-        block = Block::new();
-        block.setid(6);
-        block.row("..T.");
-        block.row(".TT.");
-        block.row("..T.");
-        block.row("....");
-        data.push(block);
-
-        // Z
-        block = Block::new();
-        block.setid(7);
-        block.row("..Z.");
-        block.row(".ZZ.");
-        block.row(".Z..");
-        block.row("....");
-        data.push(block);
-
-        Self { data }
-    }
-
-    pub fn next(&self) -> Block {
-        self.data
-            .choose(&mut thread_rng())
-            .map_or_else(Block::new, |b| b.clone())
-    }
+/// Print the command line usage and exit
+fn usage() -> ! {
+    eprintln!(
+        "usage: retris [--safe] [--cast file] [--dump dir] [--bell-rate n] [--no-bell event]\n\
+         \x20             [--mode mode] [--classic] [--invisible] [--seed n] [--puzzles dir]\n\
+         \x20             [--width n] [--height n] [--versus | --host port | --connect addr]\n\
+         \x20             [--record file | --replay file [--speed n | --export file] | --resume]\n\
+         \x20             [--ai | --bot-io] [--broadcast port | --watch addr]"
+    );
+    process::exit(1);
 }
 
-/// Start a new game
-fn engine(tetromino: Tetromino) {
-    let mut quit = false;
-    let (mut x, mut y) = (5, -1);
-    let mut game = Game::new();
-    let (mut block, mut next) = (tetromino.next(), tetromino.next());
-    game.status(&mut next);
-
-    while !quit {
-        // Handle input
-        match wgetch(*game) {
-            KEY_QUIT => quit = true,
-            KEY_RESTART => return engine(tetromino),
-            KEY_SPACE => {
-                // Jump to last possible line
-                for py in (y..getmaxy(*game)).rev() {
-                    if block.fits(&game, py, x) {
-                        game.addscore(py - y);
-                        y = py;
-                        break;
-                    }
-                }
+/// rETRIS!
+fn main() {
+    let mut safe = false;
+    let mut replay = None;
+    let mut setup = Setup::default();
+    let mut players = 1;
+    let mut host = None;
+    let mut connect = None;
+    let mut puzzle_dir = Puzzle::dir();
+    let mut first = None;
+    let mut bot = false;
+    let mut watch = None;
+    let mut output = None;
+    let mut speed = 1.0;
+    let mut recorder = Recorder::default();
+    let mut bell = Bell::new();
+    let mut config = Config::load(Config::path()).unwrap_or_else(|err| {
+        eprintln!("retris: {}", err);
+        process::exit(1);
+    });
+    let mut scores = Scores::load(Scores::path()).unwrap_or_else(|err| {
+        eprintln!("retris: cannot load high scores: {}", err);
+        process::exit(1);
+    });
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--safe" => safe = true,
+            "--cast" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let file = File::create(&path).unwrap_or_else(|err| {
+                    eprintln!("retris: {}: {}", path, err);
+                    process::exit(1);
+                });
+                recorder.cast = Some(Cast::new(file));
+            }
+            "--dump" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let dump = FrameDump::new(path.clone().into()).unwrap_or_else(|err| {
+                    eprintln!("retris: {}: {}", path, err);
+                    process::exit(1);
+                });
+                recorder.dump = Some(dump);
+            }
+            "--record" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                recorder.replay = Some(ReplayFile::new(path.into()));
+            }
+            "--replay" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let data = Replay::load(path.as_ref()).unwrap_or_else(|err| {
+                    eprintln!("retris: {}: {}", path, err);
+                    process::exit(1);
+                });
+                replay = Some(data);
+            }
+            "--export" => output = Some(args.next().unwrap_or_else(|| usage())),
+            "--mode" => {
+                let name = args.next().unwrap_or_else(|| usage());
+                setup.mode = name.parse().unwrap_or_else(|err| {
+                    eprintln!("retris: {}", err);
+                    process::exit(1);
+                });
             }
-            KEY_UP => {
-                block.rotate(&game);
+            "--seed" => {
+                let number = args.next().and_then(|arg| arg.parse().ok());
+                setup.seed = Some(number.unwrap_or_else(|| usage()));
+            }
+            "--classic" => setup.rules = Rules::Classic,
+            "--invisible" => setup.invisible = true,
+            "--puzzles" => puzzle_dir = Some(args.next().unwrap_or_else(|| usage()).into()),
+            "--resume" => setup.resume = true,
+            "--bot-io" => bot = true,
+            "--broadcast" => {
+                let port = args.next().and_then(|arg| arg.parse::<u16>().ok());
+                let port = port.unwrap_or_else(|| usage());
+                let broadcast = Broadcast::new(port).unwrap_or_else(|err| {
+                    eprintln!("retris: port {}: {}", port, err);
+                    process::exit(1);
+                });
+                recorder.broadcast = Some(broadcast);
+            }
+            "--watch" => watch = Some(args.next().unwrap_or_else(|| usage())),
+            "--ai" => {
+                setup.ai = true;
+                first.get_or_insert(Start::Game);
+            }
+            "--versus" => players = 2,
+            "--host" => {
+                let port = args.next().and_then(|arg| arg.parse::<u16>().ok());
+                host = Some(port.unwrap_or_else(|| usage()));
+                players = 2;
+            }
+            "--connect" => {
+                connect = Some(args.next().unwrap_or_else(|| usage()));
+                players = 2;
+            }
+            "--width" | "--height" => {
+                let number = args.next().and_then(|arg| arg.parse().ok());
+                let number = match number {
+                    Some(number) if (GAME_MIN..=GAME_MAX).contains(&number) => number,
+                    _ => usage(),
+                };
+                if arg == "--width" {
+                    setup.width = number;
+                } else {
+                    setup.height = number;
+                }
             }
-            KEY_DOWN => {
-                if block.fits(&game, y + 1, x) {
-                    y += 1;
+            "--speed" => {
+                let factor = args.next().and_then(|arg| arg.parse().ok());
+                match factor {
+                    Some(factor) if factor > 0.0 => speed = factor,
+                    _ => usage(),
                 }
             }
-            KEY_LEFT => {
-                if block.fits(&game, y, x - 1) {
-                    x -= 1;
+            "--bell-rate" => {
+                let rate = args.next().and_then(|arg| arg.parse().ok());
+                match rate {
+                    Some(rate) if rate > 0 => bell.set_rate(rate),
+                    _ => usage(),
                 }
             }
-            KEY_RIGHT => {
-                if block.fits(&game, y, x + 1) {
-                    x += 1;
+            "--no-bell" => {
+                let events = args.next().unwrap_or_else(|| usage());
+                for event in events.split(',') {
+                    match event.parse() {
+                        Ok(event) => bell.disable(event),
+                        Err(err) => {
+                            eprintln!("retris: {}", err);
+                            process::exit(1);
+                        }
+                    }
                 }
             }
-            _ => {}
+            _ => usage(),
         }
+    }
 
-        // Core logic
-        block.clear(*game);
-        block.setyx(y, x);
-        block.draw(*game);
+    // Replays only have the inputs of a single player, and the classic
+    // rules, invisible, zen and bot games are for a single player
+    let single =
+        setup.rules == Rules::Classic || setup.invisible || setup.mode == Mode::Zen || setup.ai;
+    if players > 1 && (replay.is_some() || recorder.replay.is_some() || single) {
+        usage();
+    }
+    let first_game = first.is_some() || setup.resume;
+    if first_game && (players > 1 || replay.is_some()) {
+        usage();
+    }
+    if bot && (players > 1 || replay.is_some() || recorder.replay.is_some() || first_game) {
+        usage();
+    }
+    if watch.is_some() && (players > 1 || replay.is_some() || first_game || bot) {
+        usage();
+    }
+    if watch.is_some() && recorder.broadcast.is_some() {
+        usage();
+    }
 
-        // Store block and create a new one if the previous doesn't fit
-        if !block.fits(&game, y + 1, x) {
-            game.store(block);
-            block = next;
-            next = tetromino.next();
-            beep();
-            x = 5;
-            y = -1;
-            game.status(&mut next);
-        } else {
-            y += 1;
+    // A replay is exported without the terminal
+    if let Some(path) = output {
+        let replay = replay.unwrap_or_else(|| usage());
+        if let Err(err) = export(&replay, path.as_ref()) {
+            eprintln!("retris: {}: {}", path, err);
+            process::exit(1);
         }
+        return;
+    }
 
-        // End game if the new block doesn't fit
-        if quit || !block.fits(&game, y, x) {
-            game.gameover();
-            game.status(&mut next);
-
-            quit = false;
-            while !quit {
-                match wgetch(*game) {
-                    KEY_QUIT => quit = true,
-                    KEY_RESTART => return engine(tetromino),
-                    _ => {}
-                }
-            }
+    // A bot plays through stdin and stdout instead of the terminal
+    if bot {
+        if let Err(err) = bot_io(setup) {
+            eprintln!("retris: {}", err);
+            process::exit(1);
         }
+        return;
+    }
+    if host.is_some() && connect.is_some() {
+        usage();
+    }
 
-        // Render output
-        game.refresh();
+    // Find the other player of a network game before the game starts,
+    // the host decides about the game
+    let mut link = None;
+    if let Some(port) = host {
+        setup.seed.get_or_insert_with(|| thread_rng().gen());
+        eprintln!("retris: waiting for a player on port {}", port);
+        link = Some(Connection::host(port, setup).unwrap_or_else(|err| {
+            eprintln!("retris: port {}: {}", port, err);
+            process::exit(1);
+        }));
+    } else if let Some(addr) = connect {
+        let (connection, host) = Connection::connect(&addr).unwrap_or_else(|err| {
+            eprintln!("retris: {}: {}", addr, err);
+            process::exit(1);
+        });
+        link = Some(connection);
+        setup = host;
     }
-}
 
-/// rETRIS!
-fn main() {
-    let tetromino = Tetromino::new();
+    if env::var("TERM").map_or(true, |term| term.is_empty()) {
+        eprintln!("retris: TERM is not set, try TERM=xterm-256color");
+        process::exit(1);
+    }
+
+    // A spectator only shows the screen of the watched game
+    if let Some(addr) = watch {
+        if let Err(err) = net::watch(&addr) {
+            eprintln!("retris: {}: {}", addr, err);
+            process::exit(1);
+        }
+        return;
+    }
 
     initscr();
     curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
     noecho();
+    cbreak();
+
+    let mut style = if safe { Style::safe() } else { Style::new() };
+    let (height, width) = replay
+        .as_ref()
+        .map_or((setup.height, setup.width), |replay| {
+            (replay.height(), replay.width())
+        });
+    if !diagnose(&mut style, height, width, players) {
+        endwin();
+        return;
+    }
 
-    if has_colors() {
+    if style.colors {
         start_color();
 
-        // Set the block colors by index
+        // Set the block colors by index, the L piece is only orange if
+        // the terminal has the extended colors
+        let orange = if COLORS() > COLOR_ORANGE.into() {
+            COLOR_ORANGE
+        } else {
+            COLOR_WHITE
+        };
         init_pair(1, COLOR_BLACK, COLOR_CYAN);
         init_pair(2, COLOR_BLACK, COLOR_BLUE);
-        init_pair(3, COLOR_BLACK, COLOR_WHITE);
+        init_pair(3, COLOR_BLACK, orange);
         init_pair(4, COLOR_BLACK, COLOR_YELLOW);
         init_pair(5, COLOR_BLACK, COLOR_GREEN);
         init_pair(6, COLOR_BLACK, COLOR_MAGENTA);
         init_pair(7, COLOR_BLACK, COLOR_RED);
+        init_pair(GARBAGE.into(), COLOR_WHITE, COLOR_BLACK);
     }
 
-    engine(tetromino);
+    // The colors can be turned off in the settings, but only turned on
+    // if the terminal has them
+    let colored = |config: &Config| Style {
+        colors: style.colors && config.colors,
+        ..style
+    };
+    if let Some(replay) = replay {
+        playback(colored(&config), &config, &replay, speed, &mut recorder);
+    } else if let Some(link) = link.as_mut() {
+        let style = colored(&config);
+        versus(style, &config, setup, Some(link), &mut recorder, &mut bell);
+    } else if players > 1 {
+        let style = colored(&config);
+        while versus(style, &config, setup, None, &mut recorder, &mut bell) {}
+    } else {
+        // The saved game is only loaded when the game is about to start,
+        // it stays where it is if rETRIS does not get that far
+        if mem::take(&mut setup.resume) {
+            match resume() {
+                Ok((state, time)) => first = Some(Start::Resume(Box::new(state), time)),
+                Err(err) => {
+                    endwin();
+                    match err.kind() {
+                        ErrorKind::NotFound => eprintln!("retris: no saved game"),
+                        _ => eprintln!("retris: cannot resume: {}", err),
+                    }
+                    process::exit(1);
+                }
+            }
+        }
+
+        // The main menu comes back after every game until the player
+        // quits, a resumed or bot game starts without it
+        loop {
+            let dir = puzzle_dir.as_deref();
+            let mut start = match first.take() {
+                Some(start) => start,
+                None => main_menu(style, &mut config, &mut setup, dir, &scores, &mut recorder),
+            };
+            if let Start::Quit = start {
+                break;
+            }
+            let style = colored(&config);
+            while play(
+                style,
+                &config,
+                setup,
+                &mut start,
+                &mut scores,
+                &mut recorder,
+                &mut bell,
+            ) {}
+        }
+    }
 
     endwin();
+
+    recorder.finish();
+    if let Some(err) = recorder.error {
+        eprintln!("retris: recording failed: {}", err);
+    }
 }
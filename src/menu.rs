@@ -0,0 +1,325 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::config::{bindable, Config};
+use crate::record::Recorder;
+use crate::term::Style;
+use crate::{leaderboard, resume, Setup, KEY_ESCAPE, KEY_NEWLINE, KEY_QUIT, KEY_RETURN, KEY_SPACE};
+use ncurses::*;
+use retris::{GameState, Mode, Puzzle, Rules, Scores, LEVEL_MAX};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::Duration;
+
+/// The game modes in the order of the menu
+const MODES: [Mode; 4] = [Mode::Marathon, Mode::Sprint, Mode::Ultra, Mode::Zen];
+
+/// The minimum width of a menu window
+const MENU_WIDTH: i32 = 32;
+
+/// The time without a key in the main menu before the bot plays a demo
+/// game, in milliseconds
+const ATTRACT_TIME: i32 = 30_000;
+
+/// What the player did in a menu
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Choice {
+    /// Select the entry
+    Select(usize),
+    /// Change the value of the entry to the previous or next one
+    Change(usize, i32),
+    /// Leave the menu
+    Back,
+    /// No key was pressed for a while
+    Idle,
+}
+
+/// What the player picked in the main menu
+pub enum Start {
+    /// Start a new game
+    Game,
+    /// Try to solve a puzzle
+    Puzzle(Puzzle),
+    /// Go on with a saved game at the time when it was saved
+    Resume(Box<GameState>, Duration),
+    /// Let the bot play a game until a key is pressed
+    Demo,
+    /// Leave rETRIS
+    Quit,
+}
+
+/// A list of entries in a window over the middle of the screen
+struct Menu {
+    /// How the window is rendered
+    style: Style,
+    /// The title above the entries
+    title: &'static str,
+    /// The highlighted entry
+    selected: usize,
+    /// The time to wait for a key in milliseconds, -1 waits forever
+    timeout: i32,
+}
+
+impl Menu {
+    /// Create a new menu with the title
+    fn new(style: Style, title: &'static str) -> Self {
+        Self {
+            style,
+            title,
+            selected: 0,
+            timeout: -1,
+        }
+    }
+
+    /// Show the entries with a note below them and wait for a choice
+    fn choose(&mut self, entries: &[String], note: &str, recorder: &mut Recorder) -> Choice {
+        let width = entries
+            .iter()
+            .map(|entry| entry.len() as i32 + 6)
+            .chain([MENU_WIDTH, note.len() as i32 + 4])
+            .max()
+            .unwrap_or(MENU_WIDTH)
+            .min(getmaxx(curscr()));
+        let note: String = note.chars().take(width as usize - 4).collect();
+        let height = entries.len() as i32 + 6;
+        let window = newwin(height, width, 1, getmaxx(curscr()) / 2 - width / 2);
+        keypad(window, true);
+        wtimeout(window, self.timeout);
+        self.selected = self.selected.min(entries.len() - 1);
+
+        let choice = loop {
+            erase();
+            refresh();
+            werase(window);
+            mvwaddstr(window, 1, (width - self.title.len() as i32) / 2, self.title);
+            for (i, entry) in entries.iter().enumerate() {
+                if i == self.selected {
+                    wattron(window, A_REVERSE());
+                }
+                mvwaddstr(window, i as i32 + 3, 3, entry);
+                wattroff(window, A_REVERSE());
+            }
+            mvwaddstr(window, height - 2, 2, &note);
+            self.style.border(window);
+            wrefresh(window);
+            recorder.frame();
+
+            let last = entries.len() - 1;
+            match wgetch(window) {
+                KEY_UP => self.selected = self.selected.checked_sub(1).unwrap_or(last),
+                KEY_DOWN => self.selected = (self.selected + 1) % entries.len(),
+                KEY_LEFT => break Choice::Change(self.selected, -1),
+                KEY_RIGHT => break Choice::Change(self.selected, 1),
+                KEY_NEWLINE | KEY_RETURN | KEY_ENTER | KEY_SPACE => {
+                    break Choice::Select(self.selected)
+                }
+                KEY_ESCAPE | KEY_QUIT => break Choice::Back,
+                ERR => break Choice::Idle,
+                _ => {}
+            }
+        };
+
+        delwin(window);
+        erase();
+        refresh();
+        choice
+    }
+
+    /// Show a prompt in place of the note and wait for any key
+    fn prompt(&self, text: &str, recorder: &mut Recorder) -> i32 {
+        let width = MENU_WIDTH.max(text.len() as i32 + 4);
+        let window = newwin(3, width, 1, getmaxx(curscr()) / 2 - width / 2);
+        keypad(window, true);
+        mvwaddstr(window, 1, 2, text);
+        self.style.border(window);
+        wrefresh(window);
+        recorder.frame();
+
+        let key = wgetch(window);
+        delwin(window);
+        key
+    }
+}
+
+/// Get the label of an on or off setting
+pub fn toggle(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Change the settings and key bindings, they are saved in the config
+/// file when leaving the screen.  Returns a note about the result.
+fn settings(style: Style, config: &mut Config, recorder: &mut Recorder) -> String {
+    let mut menu = Menu::new(style, "SETTINGS");
+    let mut note = "enter: change  q: back".to_string();
+
+    loop {
+        let bindings: Vec<_> = config.keymap.bindings().map(|(action, _)| action).collect();
+        let mut entries = vec![
+            format!("Start level: {}", config.level),
+            format!("Ghost piece: {}", toggle(config.ghost)),
+            format!("Colors:      {}", toggle(config.colors)),
+        ];
+        entries.extend(
+            bindings
+                .iter()
+                .map(|action| format!("{:<11}  {}", action, config.keymap.name(*action))),
+        );
+        entries.push("Back".to_string());
+
+        let (entry, step) = match menu.choose(&entries, &note, recorder) {
+            Choice::Select(entry) => (entry, 1),
+            Choice::Change(entry, step) => (entry, step),
+            Choice::Back | Choice::Idle => break,
+        };
+        note = "enter: change  q: back".to_string();
+        match entry {
+            0 => config.level = (config.level + step - 1).rem_euclid(LEVEL_MAX) + 1,
+            1 => config.ghost = !config.ghost,
+            2 => config.colors = !config.colors,
+            n if n < entries.len() - 1 => {
+                // Bind a single key to the action, escape keeps the old ones
+                let action = bindings[n - 3];
+                let key = menu.prompt(&format!("Press a key for {}", action), recorder);
+                if key == KEY_ESCAPE {
+                    continue;
+                } else if bindable(key) {
+                    config.keymap.bind(action, vec![key]);
+                } else {
+                    note = "This key cannot be bound".to_string();
+                }
+            }
+            _ => break,
+        }
+    }
+
+    match Config::path() {
+        Some(path) => match config.save(&path) {
+            Ok(()) => "Settings saved".to_string(),
+            Err(err) => format!("Not saved: {}", err),
+        },
+        None => "Not saved: no config directory".to_string(),
+    }
+}
+
+/// Pick one of the puzzles in the directory, or None to go back
+fn puzzles(
+    style: Style,
+    dir: Option<&Path>,
+    recorder: &mut Recorder,
+) -> Result<Option<Puzzle>, String> {
+    let dir = dir.ok_or("No puzzle directory")?;
+    let (mut list, skipped) = Puzzle::load_dir(dir).map_err(|err| err.to_string())?;
+    if let (true, Some(err)) = (list.is_empty(), skipped.first()) {
+        return Err(format!("Skipped {}", err));
+    } else if list.is_empty() {
+        return Err(format!("No puzzles in {}", dir.display()));
+    }
+
+    // The files that are not puzzles are reported instead of the keys
+    let note = match skipped.len() {
+        0 => "enter: play  q: back".to_string(),
+        1 => format!("Skipped {}", skipped[0]),
+        n => format!("Skipped {} files, {}", n, skipped[0]),
+    };
+    let entries: Vec<String> = list
+        .iter()
+        .enumerate()
+        .map(|(i, puzzle)| format!("{:>2}. {}", i + 1, puzzle.name()))
+        .collect();
+
+    let mut menu = Menu::new(style, "PUZZLES");
+    loop {
+        match menu.choose(&entries, &note, recorder) {
+            Choice::Select(entry) => return Ok(Some(list.swap_remove(entry))),
+            Choice::Change(..) | Choice::Idle => {}
+            Choice::Back => return Ok(None),
+        }
+    }
+}
+
+/// Show the main menu until the player starts a new game, a puzzle
+/// from the puzzle directory or resumes the saved game.  The bot plays
+/// a demo game when the menu was left alone for a while.
+pub fn main_menu(
+    style: Style,
+    config: &mut Config,
+    setup: &mut Setup,
+    puzzle_dir: Option<&Path>,
+    scores: &Scores,
+    recorder: &mut Recorder,
+) -> Start {
+    let mut menu = Menu::new(style, "rETRIS");
+    let mut note = "enter: select  q: quit".to_string();
+    menu.timeout = ATTRACT_TIME;
+
+    loop {
+        let entries = [
+            "New game".to_string(),
+            "Resume game".to_string(),
+            format!("Mode: {}", setup.mode),
+            format!("Rules: {}", setup.rules),
+            format!("Invisible: {}", toggle(setup.invisible)),
+            "Puzzles".to_string(),
+            "Settings".to_string(),
+            "High scores".to_string(),
+            "Quit".to_string(),
+        ];
+        let (entry, step) = match menu.choose(&entries, &note, recorder) {
+            Choice::Select(entry) => (entry, 1),
+            Choice::Change(entry @ 2..=4, step) => (entry, step),
+            Choice::Change(..) => continue,
+            Choice::Back => return Start::Quit,
+            Choice::Idle => return Start::Demo,
+        };
+        note = "enter: select  q: quit".to_string();
+        match entry {
+            0 => return Start::Game,
+            1 => match resume() {
+                Ok((state, time)) => return Start::Resume(Box::new(state), time),
+                Err(err) if err.kind() == ErrorKind::NotFound => note = "No saved game".to_string(),
+                Err(err) => note = format!("Cannot resume: {}", err),
+            },
+            2 => {
+                let mode = MODES.iter().position(|mode| *mode == setup.mode);
+                let mode = mode.unwrap_or_default() as i32 + step;
+                setup.mode = MODES[mode.rem_euclid(MODES.len() as i32) as usize];
+            }
+            3 => {
+                setup.rules = match setup.rules {
+                    Rules::Guideline => Rules::Classic,
+                    Rules::Classic => Rules::Guideline,
+                }
+            }
+            4 => setup.invisible = !setup.invisible,
+            5 => match puzzles(style, puzzle_dir, recorder) {
+                Ok(Some(puzzle)) => return Start::Puzzle(puzzle),
+                Ok(None) => {}
+                Err(err) => note = err,
+            },
+            6 => note = settings(style, config, recorder),
+            7 => {
+                leaderboard(style, scores, None, "Press any key");
+                recorder.frame();
+                wgetch(stdscr());
+            }
+            _ => return Start::Quit,
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::{Setup, KEY_QUIT};
+use ncurses::*;
+use retris::{Input, GAME_MAX, GAME_MIN, GARBAGE, PIECE_TYPES};
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The first line that both ends of a connection send
+const NET_HEADER: &str = "retris versus 1";
+
+/// The longest time to wait for a message in every frame
+const NET_WAIT: Duration = Duration::from_millis(1);
+
+/// The longest line that a player can send, the board of the largest
+/// field fits into it
+const NET_LINE_MAX: usize = (GAME_MAX * GAME_MAX) as usize + 64;
+
+/// The longest time to wait for the screen of a watched game
+const WATCH_WAIT: Duration = Duration::from_millis(10);
+
+/// A message between the two players of a network game, one per line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// The seed, mode and field size of the game, sent by the host
+    Setup(Setup),
+    /// An input of the game of the sender
+    Input(Input),
+    /// Rows of garbage for the receiver, with a hole in a column
+    Garbage(usize, i32),
+    /// The locked cells of the field of the sender
+    Board(Vec<u8>),
+    /// The sender left the game
+    Bye,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Setup(setup) => write!(
+                f,
+                "setup {} {} {}x{}",
+                setup.seed.unwrap_or_default(),
+                setup.mode,
+                setup.height,
+                setup.width
+            ),
+            Message::Input(input) => write!(f, "input {}", input),
+            Message::Garbage(rows, hole) => write!(f, "garbage {} {}", rows, hole),
+            Message::Board(cells) => {
+                let cells: String = cells.iter().map(|id| (b'0' + id) as char).collect();
+                write!(f, "board {}", cells)
+            }
+            Message::Bye => write!(f, "bye"),
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid message: {}", s);
+        let (kind, args) = s.split_once(' ').unwrap_or((s, ""));
+        let mut words = args.split_whitespace();
+        let message = match kind {
+            "setup" => {
+                let seed = words.next().and_then(|seed| seed.parse().ok());
+                let mode = words.next().and_then(|mode| mode.parse().ok());
+                let (height, width) = words
+                    .next()
+                    .and_then(|size| size.split_once('x'))
+                    .and_then(|(height, width)| Some((height.parse().ok()?, width.parse().ok()?)))
+                    .filter(|(height, width)| {
+                        (GAME_MIN..=GAME_MAX).contains(height)
+                            && (GAME_MIN..=GAME_MAX).contains(width)
+                    })
+                    .ok_or_else(invalid)?;
+                Message::Setup(Setup {
+                    seed: Some(seed.ok_or_else(invalid)?),
+                    mode: mode.ok_or_else(invalid)?,
+                    height,
+                    width,
+                    ..Setup::default()
+                })
+            }
+            "input" => Message::Input(args.parse()?),
+            "garbage" => {
+                let rows = words.next().and_then(|rows| rows.parse().ok());
+                let hole = words.next().and_then(|hole| hole.parse().ok());
+                let hole = hole.filter(|hole| (0..GAME_MAX).contains(hole));
+                Message::Garbage(rows.ok_or_else(invalid)?, hole.ok_or_else(invalid)?)
+            }
+            "board" => Message::Board(
+                args.bytes()
+                    .map(|c| match c.checked_sub(b'0') {
+                        // Only the pieces and the garbage have cells
+                        Some(id) if id <= PIECE_TYPES as u8 || id == GARBAGE => Ok(id),
+                        _ => Err(invalid()),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            "bye" => Message::Bye,
+            _ => return Err(invalid()),
+        };
+        Ok(message)
+    }
+}
+
+/// The connection to the other player of a network game
+pub struct Connection {
+    /// The socket
+    stream: TcpStream,
+    /// Received data that does not end with a newline yet
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    /// Start a connection on the socket and exchange the header lines
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        let mut link = Self {
+            stream,
+            buffer: Vec::new(),
+        };
+        link.stream.set_nodelay(true)?;
+        link.write(NET_HEADER)?;
+        match link.line()? {
+            Some(line) if line == NET_HEADER => {}
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "not a rETRIS player",
+                ))
+            }
+        }
+        link.stream.set_read_timeout(Some(NET_WAIT))?;
+        Ok(link)
+    }
+
+    /// Wait for a player to connect to the port and send the setup of
+    /// the game
+    pub fn host(port: u16, setup: Setup) -> io::Result<Self> {
+        let listener =
+            TcpListener::bind(("::", port)).or_else(|_| TcpListener::bind(("0.0.0.0", port)))?;
+        let (stream, _) = listener.accept()?;
+        let mut link = Self::new(stream)?;
+        link.send(&Message::Setup(setup))?;
+        Ok(link)
+    }
+
+    /// Connect to a host and get the setup of the game
+    pub fn connect(addr: &str) -> io::Result<(Self, Setup)> {
+        let mut link = Self::new(TcpStream::connect(addr)?)?;
+        loop {
+            match link.recv()? {
+                Some(Message::Setup(setup)) => return Ok((link, setup)),
+                Some(_) => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "expected the setup of the game",
+                    ))
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Write a line to the other player
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        self.stream.write_all(format!("{}\n", line).as_bytes())
+    }
+
+    /// Read the next complete line, if it was received
+    fn line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|c| *c == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim_end().to_string();
+                return Ok(Some(line));
+            }
+
+            // A line that never ends is not a message
+            if self.buffer.len() > NET_LINE_MAX {
+                return Err(io::Error::new(ErrorKind::InvalidData, "line too long"));
+            }
+
+            let mut data = [0; 4096];
+            match self.stream.read(&mut data) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(length) => self.buffer.extend_from_slice(&data[..length]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(err) if err.kind() == ErrorKind::TimedOut => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Send a message to the other player
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        self.write(&message.to_string())
+    }
+
+    /// Receive the next message from the other player, if there is one
+    pub fn recv(&mut self) -> io::Result<Option<Message>> {
+        match self.line()? {
+            Some(line) => line
+                .parse()
+                .map(Some)
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Watch the game of another player that is broadcast on the address:
+/// its screen is copied to the terminal until the game ends or q is
+/// pressed
+pub fn watch(addr: &str) -> io::Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(WATCH_WAIT))?;
+
+    // Curses only reads the keys, the broadcast draws the screen
+    initscr();
+    curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+    noecho();
+    cbreak();
+    timeout(0);
+    refresh();
+
+    let stdout = io::stdout();
+    let result = loop {
+        if getch() == KEY_QUIT {
+            break Ok(());
+        }
+
+        let mut data = [0; 4096];
+        match stream.read(&mut data) {
+            Ok(0) => break Ok(()),
+            Ok(length) => {
+                let mut out = stdout.lock();
+                if let Err(err) = out.write_all(&data[..length]).and_then(|_| out.flush()) {
+                    break Err(err);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) if err.kind() == ErrorKind::TimedOut => {}
+            Err(err) => break Err(err),
+        }
+    };
+
+    endwin();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use retris::{Mode, Rotation};
+
+    #[test]
+    fn formats_and_parses_messages() {
+        let setup = Setup {
+            seed: Some(42),
+            mode: Mode::Sprint,
+            height: 24,
+            width: 12,
+            ..Setup::default()
+        };
+        let messages = [
+            (Message::Setup(setup), "setup 42 Sprint 24x12"),
+            (
+                Message::Input(Input::Rotate(Rotation::CounterClockwise)),
+                "input rotate ccw",
+            ),
+            (Message::Input(Input::Garbage(2, 5)), "input garbage 2 5"),
+            (Message::Garbage(4, 9), "garbage 4 9"),
+            (Message::Board(vec![0, 1, 7, GARBAGE]), "board 0178"),
+            (Message::Bye, "bye"),
+        ];
+        for (message, line) in messages.iter() {
+            assert_eq!(message.to_string(), *line);
+            assert_eq!(line.parse(), Ok(message.clone()));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_messages() {
+        for invalid in [
+            "",
+            "hello",
+            "setup 42 Sprint",
+            "setup 42 Sprint 24",
+            "setup 42 Sprint 3x10",
+            "setup 42 Sprint 20x101",
+            "setup x Sprint 20x10",
+            "setup 42 Fast 20x10",
+            "input jump",
+            "garbage 2",
+            "garbage -1 3",
+            "garbage x 3",
+            "garbage 2 -1",
+            "garbage 2 100",
+            "board 019",
+            "board 01x",
+        ]
+        .iter()
+        {
+            assert!(invalid.parse::<Message>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng, SeedableRng};
+
+pub const PIECE_WIDTH: usize = 4;
+pub const PIECE_SIZE: usize = PIECE_WIDTH * PIECE_WIDTH;
+
+/// SRS wall kicks of the J, L, S, T and Z pieces for clockwise rotations
+/// from the spawn state 0, R, 2, and L. The offsets are (x, y) with y
+/// pointing up, as in the guideline tables.
+const KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+
+/// SRS wall kicks of the I piece for clockwise rotations
+const KICKS_I: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+
+/// The O piece does not kick
+const KICKS_O: [[(i32, i32); 1]; 4] = [[(0, 0)]; 4];
+
+/// SRS has no 180 degree rotations, only try to kick the piece one row up
+const KICKS_180: [(i32, i32); 2] = [(0, 0), (0, 1)];
+
+/// The direction of a rotation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotation {
+    /// 90 degrees clockwise
+    Clockwise,
+    /// 90 degrees counter-clockwise
+    CounterClockwise,
+    /// 180 degrees
+    Half,
+}
+
+/// A tetromino piece
+#[derive(Debug, Clone)]
+pub struct Piece {
+    /// The 4x4 tetromino matrix
+    data: [u8; PIECE_SIZE],
+    /// The number of rows that have been set
+    index: usize,
+    /// The current y location on the field, negative is above the field
+    pub y: i32,
+    /// The current x location on the field
+    pub x: i32,
+    /// The individual id of the tetromino type
+    id: u8,
+    /// The rotation state, 0 is the spawn state, then clockwise
+    rotation: usize,
+}
+
+impl Piece {
+    /// Return a new "empty" piece
+    pub fn new() -> Self {
+        Self {
+            data: b"................".to_owned(),
+            index: 0,
+            x: 0,
+            y: 0,
+            id: 0,
+            rotation: 0,
+        }
+    }
+
+    /// Get the individual id of the tetromino type
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Set the individual id of the tetromino type
+    pub fn setid(&mut self, id: u8) {
+        self.id = id;
+    }
+
+    /// Get the rotation state, from 0 to 3 clockwise
+    pub fn rotation(&self) -> usize {
+        self.rotation
+    }
+
+    /// Set the rotation state that the matrix is in
+    pub fn setrotation(&mut self, rotation: usize) {
+        self.rotation = rotation % 4;
+    }
+
+    /// Get the size of the box that the piece rotates in,
+    /// 4x4 for I, 3x3 in the upper left corner for the others
+    fn size(&self) -> usize {
+        match self.name() {
+            'I' => 4,
+            _ => 3,
+        }
+    }
+
+    /// Get the letter of the tetromino type
+    pub fn name(&self) -> char {
+        self.data
+            .iter()
+            .find(|c| **c != b'.')
+            .map_or(' ', |c| *c as char)
+    }
+
+    /// Set the next row of the piece to turn it into a tetromino
+    pub fn row(&mut self, row: &str) {
+        let i = self.index;
+        if i >= PIECE_WIDTH || row.len() != PIECE_WIDTH {
+            return;
+        }
+        self.data[(i * PIECE_WIDTH)..(i * PIECE_WIDTH + PIECE_WIDTH)]
+            .copy_from_slice(row.as_bytes());
+        self.index = i + 1;
+    }
+
+    /// Store the coordinates of the piece
+    pub fn setyx(&mut self, y: i32, x: i32) {
+        self.y = y;
+        self.x = x;
+    }
+
+    /// Get the coordinates of an index in the matrix
+    pub fn getyx(idx: usize) -> (usize, usize) {
+        (idx / PIECE_WIDTH, idx % PIECE_WIDTH)
+    }
+
+    /// Iterate over the field coordinates of all pixels of the piece
+    pub fn pixels(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c != b'.')
+            .map(move |(i, _)| {
+                let (y, x) = Self::getyx(i);
+                (self.y + y as i32, self.x + x as i32)
+            })
+    }
+
+    /// Get the offset of the leftmost pixel of the piece
+    pub fn left(&self) -> i32 {
+        self.pixels().map(|(_, x)| x - self.x).min().unwrap_or(0)
+    }
+
+    /// Rotate the piece matrix in the specified direction
+    pub fn rotate(&mut self, rotation: Rotation) {
+        // The O piece looks the same in every rotation state
+        if self.name() != 'O' {
+            let mut new = *b"................";
+            let n = self.size();
+
+            for (i, c) in self.data.iter().enumerate() {
+                let (y, x) = Self::getyx(i);
+                if y >= n || x >= n {
+                    continue;
+                }
+                let idx = match rotation {
+                    Rotation::Clockwise => x * PIECE_WIDTH + n - 1 - y,
+                    Rotation::CounterClockwise => (n - 1 - x) * PIECE_WIDTH + y,
+                    Rotation::Half => (n - 1 - y) * PIECE_WIDTH + n - 1 - x,
+                };
+                new[idx] = *c;
+            }
+
+            self.data = new;
+        }
+        self.rotation = match rotation {
+            Rotation::Clockwise => (self.rotation + 1) % 4,
+            Rotation::CounterClockwise => (self.rotation + 3) % 4,
+            Rotation::Half => (self.rotation + 2) % 4,
+        };
+    }
+
+    /// Get the field offsets (y, x) to try when rotating in the specified
+    /// direction from the current rotation state
+    pub fn kicks(&self, rotation: Rotation) -> Vec<(i32, i32)> {
+        let table = |state: usize| -> &[(i32, i32)] {
+            match self.name() {
+                'I' => &KICKS_I[state],
+                'O' => &KICKS_O[state],
+                _ => &KICKS[state],
+            }
+        };
+        let kicks: Vec<(i32, i32)> = match rotation {
+            Rotation::Clockwise => table(self.rotation).to_vec(),
+            // The counter-clockwise kicks are the reversed clockwise
+            // kicks from the target state
+            Rotation::CounterClockwise => table((self.rotation + 3) % 4)
+                .iter()
+                .map(|(x, y)| (-x, -y))
+                .collect(),
+            Rotation::Half => KICKS_180.to_vec(),
+        };
+        kicks.iter().map(|(x, y)| (-y, *x)).collect()
+    }
+}
+
+impl Default for Piece {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// All tetromino pieces and a randomizer that deals them, a 7-bag or
+/// the reroll-once randomizer of the classic game
+#[derive(Debug, Clone)]
+pub struct Tetromino {
+    /// A vector of all tetrominos (I, J, L, O, S, T, Z)
+    data: Vec<Piece>,
+    /// The pieces that are left in the current bag
+    bag: Vec<Piece>,
+    /// The random number generator that shuffles the bags
+    rng: StdRng,
+    /// Roll each piece once and again on a repeat instead of using bags
+    reroll: bool,
+    /// The id of the previous piece of the reroll randomizer
+    last: u8,
+    /// The number of pieces that were dealt
+    dealt: usize,
+}
+
+impl Tetromino {
+    /// Create the tetrominos in their guideline spawn states, flat side
+    /// down, the seed determines the order of the pieces
+    pub fn new(seed: u64) -> Self {
+        let mut data = Vec::new();
+        let mut piece;
+
+        // I
+        piece = Piece::new();
+        piece.setid(1);
+        piece.row("....");
+        piece.row("IIII");
+        piece.row("....");
+        piece.row("....");
+        data.push(piece);
+
+        // J
+        piece = Piece::new();
+        piece.setid(2);
+        piece.row("J...");
+        piece.row("JJJ.");
+        piece.row("....");
+        piece.row("....");
+        data.push(piece);
+
+        // L
+        piece = Piece::new();
+        piece.setid(3);
+        piece.row("..L.");
+        piece.row("LLL.");
+        piece.row("....");
+        piece.row("....");
+        data.push(piece);
+
+        // O
+        piece = Piece::new();
+        piece.setid(4);
+        piece.row(".OO.");
+        piece.row(".OO.");
+        piece.row("....");
+        piece.row("....");
+        data.push(piece);
+
+        // S
+        piece = Piece::new();
+        piece.setid(5);
+        piece.row(".SS.");
+        piece.row("SS..");
+        piece.row("....");
+        piece.row("....");
+        data.push(piece);
+
+        // T
+        piece = Piece::new();
+        piece.setid(6);
+        piece.row(".T..");
+        piece.row("TTT.");
+        piece.row("....");
+        piece.row("....");
+        data.push(piece);
+
+        // Z
+        piece = Piece::new();
+        piece.setid(7);
+        piece.row("ZZ..");
+        piece.row(".ZZ.");
+        piece.row("....");
+        piece.row("....");
+        data.push(piece);
+
+        Self {
+            data,
+            bag: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
+            reroll: false,
+            last: 0,
+            dealt: 0,
+        }
+    }
+
+    /// Create the tetrominos with the randomizer of the classic game,
+    /// which rolls the piece again if it repeats the previous one
+    pub fn classic(seed: u64) -> Self {
+        Self {
+            reroll: true,
+            ..Self::new(seed)
+        }
+    }
+
+    /// Get a piece by its id
+    pub fn get(&self, id: u8) -> Option<&Piece> {
+        self.data.iter().find(|p| p.id == id)
+    }
+
+    /// The number of pieces that were dealt since the start, the same
+    /// seed and number restore the order of the following pieces
+    pub fn dealt(&self) -> usize {
+        self.dealt
+    }
+}
+
+impl Iterator for Tetromino {
+    type Item = Piece;
+
+    /// Deal the next piece from the bag. Each bag contains all seven
+    /// pieces in random order, so no piece is missing for long.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dealt += 1;
+        if self.reroll {
+            // Roll one of eight, a repeat or the eighth rolls one of seven
+            let count = self.data.len();
+            let mut i = self.rng.gen_range(0, count + 1);
+            if i == count || self.data[i].id == self.last {
+                i = self.rng.gen_range(0, count);
+            }
+            self.last = self.data[i].id;
+            return Some(self.data[i].clone());
+        }
+
+        if self.bag.is_empty() {
+            self.bag = self.data.clone();
+            self.bag.shuffle(&mut self.rng);
+        }
+        self.bag.pop()
+    }
+}
+
+impl Default for Tetromino {
+    fn default() -> Self {
+        Self::new(thread_rng().gen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get a piece in its spawn state by its letter
+    fn piece(name: char) -> Piece {
+        let tetromino = Tetromino::new(0);
+        let piece = tetromino.data.iter().find(|piece| piece.name() == name);
+        piece.cloned().unwrap()
+    }
+
+    /// Get the pixels of a piece at the origin, row by row
+    fn shape(piece: &Piece) -> Vec<(i32, i32)> {
+        let mut piece = piece.clone();
+        piece.setyx(0, 0);
+        piece.pixels().collect()
+    }
+
+    #[test]
+    fn rotates_clockwise_through_all_states() {
+        let mut t = piece('T');
+        let spawn = shape(&t);
+        assert_eq!(spawn, [(0, 1), (1, 0), (1, 1), (1, 2)]);
+
+        t.rotate(Rotation::Clockwise);
+        assert_eq!(t.rotation(), 1);
+        assert_eq!(shape(&t), [(0, 1), (1, 1), (1, 2), (2, 1)]);
+        t.rotate(Rotation::Clockwise);
+        assert_eq!(t.rotation(), 2);
+        assert_eq!(shape(&t), [(1, 0), (1, 1), (1, 2), (2, 1)]);
+        t.rotate(Rotation::Clockwise);
+        assert_eq!(t.rotation(), 3);
+        assert_eq!(shape(&t), [(0, 1), (1, 0), (1, 1), (2, 1)]);
+        t.rotate(Rotation::Clockwise);
+        assert_eq!(t.rotation(), 0);
+        assert_eq!(shape(&t), spawn);
+    }
+
+    #[test]
+    fn rotates_counter_clockwise_and_half() {
+        // Turning back once or by half is the same as turning clockwise
+        // three times or twice
+        let clockwise = |name: char, turns: usize| {
+            let mut piece = piece(name);
+            for _ in 0..turns {
+                piece.rotate(Rotation::Clockwise);
+            }
+            piece
+        };
+        for name in "IJLOSTZ".chars() {
+            let mut ccw = piece(name);
+            ccw.rotate(Rotation::CounterClockwise);
+            assert_eq!(ccw.rotation(), 3, "{}", name);
+            assert_eq!(shape(&ccw), shape(&clockwise(name, 3)), "{}", name);
+
+            let mut half = piece(name);
+            half.rotate(Rotation::Half);
+            assert_eq!(half.rotation(), 2, "{}", name);
+            assert_eq!(shape(&half), shape(&clockwise(name, 2)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rotates_in_its_box() {
+        // The I piece turns in a 4x4 box, the O piece does not turn
+        let mut i = piece('I');
+        i.rotate(Rotation::Clockwise);
+        assert_eq!(shape(&i), [(0, 2), (1, 2), (2, 2), (3, 2)]);
+        i.rotate(Rotation::Clockwise);
+        assert_eq!(shape(&i), [(2, 0), (2, 1), (2, 2), (2, 3)]);
+
+        let mut o = piece('O');
+        let spawn = shape(&o);
+        o.rotate(Rotation::Clockwise);
+        assert_eq!(shape(&o), spawn);
+        assert_eq!(o.rotation(), 1);
+    }
+
+    #[test]
+    fn kicks_clockwise() {
+        // The offsets are field rows and columns, down is positive
+        let t = piece('T');
+        assert_eq!(
+            t.kicks(Rotation::Clockwise),
+            [(0, 0), (0, -1), (-1, -1), (2, 0), (2, -1)]
+        );
+        let i = piece('I');
+        assert_eq!(
+            i.kicks(Rotation::Clockwise),
+            [(0, 0), (0, -2), (0, 1), (1, -2), (-2, 1)]
+        );
+        let o = piece('O');
+        assert_eq!(o.kicks(Rotation::Clockwise), [(0, 0)]);
+        assert_eq!(t.kicks(Rotation::Half), [(0, 0), (-1, 0)]);
+    }
+
+    #[test]
+    fn kicks_counter_clockwise() {
+        // The guideline tables for 0->L, R->0, 2->R and L->2 as (x, y)
+        // with y pointing up
+        let jlstz = [
+            [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+            [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+            [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+            [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+        ];
+        let i = [
+            [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+            [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+            [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+            [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+        ];
+        for name in "IJLSTZ".chars() {
+            let table = if name == 'I' { &i } else { &jlstz };
+            let mut piece = piece(name);
+            for (state, kicks) in table.iter().enumerate() {
+                let expected: Vec<(i32, i32)> = kicks.iter().map(|(x, y)| (-y, *x)).collect();
+                assert_eq!(
+                    piece.kicks(Rotation::CounterClockwise),
+                    expected,
+                    "{} from state {}",
+                    name,
+                    state
+                );
+                piece.rotate(Rotation::Clockwise);
+            }
+        }
+    }
+
+    #[test]
+    fn deals_from_bags() {
+        let names: Vec<char> = Tetromino::new(7).take(70).map(|p| p.name()).collect();
+
+        // Every bag of seven deals each piece once
+        for bag in names.chunks(7) {
+            let mut bag = bag.to_vec();
+            bag.sort_unstable();
+            assert_eq!(bag, ['I', 'J', 'L', 'O', 'S', 'T', 'Z']);
+        }
+
+        // So no piece is missing for more than twelve pieces in a row
+        for name in "IJLOSTZ".chars() {
+            let dealt: Vec<usize> = (0..names.len()).filter(|i| names[*i] == name).collect();
+            assert!(dealt.windows(2).all(|pair| pair[1] - pair[0] <= 13));
+        }
+
+        // The same seed deals the same pieces
+        let again: Vec<char> = Tetromino::new(7).take(70).map(|p| p.name()).collect();
+        assert_eq!(names, again);
+    }
+}
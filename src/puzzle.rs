@@ -0,0 +1,380 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::game::{GameState, GAME_HEIGHT, GAME_MAX, GAME_MIN};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The first line of a puzzle file
+const PUZZLE_HEADER: &str = "retris puzzle 1";
+/// The letters of the pieces
+const PIECE_NAMES: &str = "IJLOSTZ";
+
+/// The objective of a puzzle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Goal {
+    /// Clear a number of lines
+    Lines(usize),
+    /// Clear four rows at once
+    Tetris,
+    /// Clear all cells of the field
+    Clear,
+    /// Clear two rows at once by spinning a T piece into its place
+    TSpinDouble,
+}
+
+impl Goal {
+    /// Check if the goal is reached after a piece cleared the rows,
+    /// maybe with a T-spin
+    pub(crate) fn reached(self, lines: usize, rows: usize, tspin: bool, cells: &[u8]) -> bool {
+        match self {
+            Goal::Lines(goal) => lines >= goal,
+            Goal::Tetris => rows >= 4,
+            Goal::Clear => rows > 0 && cells.iter().all(|id| *id == 0),
+            Goal::TSpinDouble => tspin && rows == 2,
+        }
+    }
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Goal::Lines(1) => write!(f, "Clear a line"),
+            Goal::Lines(lines) => write!(f, "Clear {} lines", lines),
+            Goal::Tetris => write!(f, "Clear four rows at once"),
+            Goal::Clear => write!(f, "Clear the whole field"),
+            Goal::TSpinDouble => write!(f, "Clear two rows with a T-spin"),
+        }
+    }
+}
+
+impl FromStr for Goal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("lines"), Some(lines), None) => lines
+                .parse()
+                .ok()
+                .filter(|lines| *lines > 0)
+                .map(Goal::Lines)
+                .ok_or_else(|| format!("invalid lines: {}", lines)),
+            (Some("tetris"), None, None) => Ok(Goal::Tetris),
+            (Some("clear"), None, None) => Ok(Goal::Clear),
+            (Some("tspin-double"), None, None) => Ok(Goal::TSpinDouble),
+            _ => Err(format!("unknown goal: {}", s)),
+        }
+    }
+}
+
+/// A puzzle with a prepared field, a fixed sequence of pieces and a goal
+#[derive(Debug, Clone)]
+pub struct Puzzle {
+    /// The name of the puzzle
+    name: String,
+    /// The objective
+    goal: Goal,
+    /// The letters of the pieces in the order they are dealt
+    pieces: Vec<char>,
+    /// The bottom rows of the field, top to bottom
+    board: Vec<String>,
+}
+
+impl Puzzle {
+    /// Get the default location of the puzzles,
+    /// `$XDG_DATA_HOME/retris/puzzles` or `~/.local/share/retris/puzzles`
+    pub fn dir() -> Option<PathBuf> {
+        let data = env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+        Some(data.join("retris").join("puzzles"))
+    }
+
+    /// The name of the puzzle
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The objective of the puzzle
+    pub fn goal(&self) -> Goal {
+        self.goal
+    }
+
+    /// Start a new game with the field, pieces and goal of the puzzle
+    pub fn start(&self) -> GameState {
+        let width = self.board[0].len() as i32;
+        let height = GAME_HEIGHT.max(self.board.len() as i32);
+        let mut state = GameState::with_size(0, height, width);
+
+        // The rows of the board are at the bottom of the field
+        let mut cells = vec![0; (height * width) as usize];
+        let offset = cells.len() - self.board.len() * width as usize;
+        let board = self.board.iter().flat_map(|row| row.chars());
+        for (cell, name) in cells[offset..].iter_mut().zip(board) {
+            *cell = state.id(name).unwrap_or_default();
+        }
+        state.set_cells(&cells);
+        state.set_sequence(&self.pieces);
+        state.set_goal(Some(self.goal));
+
+        state
+    }
+
+    /// Load a puzzle file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        let error = |n: usize, what: String| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: line {}: {}", path.display(), n + 1, what),
+            )
+        };
+
+        let mut lines = data.lines().enumerate();
+        match lines.next() {
+            Some((_, PUZZLE_HEADER)) => {}
+            _ => return Err(error(0, "not a puzzle file".to_string())),
+        }
+
+        // The settings are followed by the rows of the board
+        let mut name = None;
+        let mut goal = None;
+        let mut pieces = Vec::new();
+        let mut board = Vec::new();
+        for (n, line) in lines.by_ref() {
+            if let Some(value) = line.strip_prefix("name ") {
+                name = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix("goal ") {
+                goal = Some(value.parse().map_err(|err| error(n, err))?);
+            } else if let Some(value) = line.strip_prefix("pieces ") {
+                pieces = value.chars().filter(|c| !c.is_whitespace()).collect();
+                if let Some(c) = pieces.iter().find(|c| !PIECE_NAMES.contains(**c)) {
+                    return Err(error(n, format!("unknown piece: {}", c)));
+                }
+            } else if line == "board" {
+                break;
+            } else if !line.trim().is_empty() {
+                return Err(error(n, format!("unknown setting: {}", line)));
+            }
+        }
+        for (n, line) in lines {
+            if let Some(c) = line
+                .chars()
+                .find(|c| *c != '.' && *c != '#' && !PIECE_NAMES.contains(*c))
+            {
+                return Err(error(n, format!("invalid cell: {}", c)));
+            }
+            let width = board.first().map_or(line.len(), String::len);
+            if line.len() != width || !(GAME_MIN as usize..=GAME_MAX as usize).contains(&width) {
+                return Err(error(n, format!("invalid row width: {}", line.len())));
+            }
+            if board.len() >= GAME_MAX as usize {
+                return Err(error(n, "too many rows".to_string()));
+            }
+            board.push(line.to_string());
+        }
+
+        let missing = |what: &str| error(0, format!("missing {}", what));
+        Ok(Self {
+            name: name.ok_or_else(|| missing("name"))?,
+            goal: goal.ok_or_else(|| missing("goal"))?,
+            pieces: Some(pieces)
+                .filter(|pieces| !pieces.is_empty())
+                .ok_or_else(|| missing("pieces"))?,
+            board: Some(board)
+                .filter(|board| !board.is_empty())
+                .ok_or_else(|| missing("board"))?,
+        })
+    }
+
+    /// Load all puzzle files of a directory, ordered by their file names.
+    /// A file that is not a valid puzzle is skipped, its error is
+    /// returned with the puzzles.
+    pub fn load_dir(dir: &Path) -> io::Result<(Vec<Self>, Vec<io::Error>)> {
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.is_file());
+        paths.sort();
+
+        let mut puzzles = Vec::new();
+        let mut errors = Vec::new();
+        for path in paths.iter() {
+            match Self::load(path) {
+                Ok(puzzle) => puzzles.push(puzzle),
+                Err(err) => errors.push(err),
+            }
+        }
+        Ok((puzzles, errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameOver;
+    use crate::piece::Rotation;
+    use crate::replay::Input;
+    use std::process;
+
+    /// Write a file for a test into a temporary directory
+    fn temp(dir: &str, name: &str, data: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("retris-{}-{}", process::id(), dir));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_goals() {
+        assert_eq!("lines 3".parse(), Ok(Goal::Lines(3)));
+        assert_eq!("tetris".parse(), Ok(Goal::Tetris));
+        assert_eq!("clear".parse(), Ok(Goal::Clear));
+        assert_eq!("tspin-double".parse(), Ok(Goal::TSpinDouble));
+        for invalid in ["", "lines", "lines 0", "lines x", "tetris 4", "spin"].iter() {
+            assert!(invalid.parse::<Goal>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn reaches_goals() {
+        assert!(Goal::Lines(2).reached(2, 1, false, &[1]));
+        assert!(!Goal::Lines(2).reached(1, 1, false, &[1]));
+        assert!(Goal::Tetris.reached(4, 4, false, &[1]));
+        assert!(!Goal::Tetris.reached(3, 3, false, &[1]));
+        assert!(Goal::Clear.reached(2, 2, false, &[0, 0]));
+        assert!(!Goal::Clear.reached(2, 2, false, &[0, 1]));
+        assert!(Goal::TSpinDouble.reached(2, 2, true, &[1]));
+        assert!(!Goal::TSpinDouble.reached(2, 2, false, &[1]));
+        assert!(!Goal::TSpinDouble.reached(1, 1, true, &[1]));
+    }
+
+    #[test]
+    fn loads_a_puzzle() {
+        let data = "retris puzzle 1\nname Test\ngoal lines 2\npieces I O\n\
+                    board\n#.........\nIIII......\n";
+        let path = temp("load", "puzzle.txt", data);
+        let puzzle = Puzzle::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(puzzle.name(), "Test");
+        assert_eq!(puzzle.goal(), Goal::Lines(2));
+        assert_eq!(puzzle.pieces, ['I', 'O']);
+        assert_eq!(puzzle.board, ["#.........", "IIII......"]);
+
+        let state = puzzle.start();
+        assert_eq!((state.height(), state.width()), (GAME_HEIGHT, 10));
+        assert_eq!(state.name(state.cell(GAME_HEIGHT - 1, 3)), 'I');
+        assert_eq!(state.name(state.cell(GAME_HEIGHT - 2, 0)), '#');
+        assert_eq!(state.piece().name(), 'I');
+        assert_eq!(state.goal(), Some(Goal::Lines(2)));
+    }
+
+    #[test]
+    fn rejects_invalid_puzzles() {
+        let valid = "retris puzzle 1\nname Test\ngoal tetris\npieces I\nboard\n#...\n";
+        let path = temp("invalid", "puzzle.txt", valid);
+        assert!(Puzzle::load(&path).is_ok());
+        let too_many = format!("#...\n{}", "#...\n".repeat(GAME_MAX as usize));
+        for (from, to) in [
+            ("retris puzzle 1", "retris puzzle 2"),
+            ("name Test\n", ""),
+            ("goal tetris", "goal spin"),
+            ("goal tetris\n", ""),
+            ("pieces I", "pieces X"),
+            ("pieces I", "pieces"),
+            ("pieces I\n", "pieces I\nsize 4\n"),
+            ("#...\n", ""),
+            ("#...\n", "#..x\n"),
+            ("#...\n", "#...\n#....\n"),
+            ("#...\n", "#..\n"),
+            ("#...\n", &too_many),
+        ]
+        .iter()
+        {
+            let data = valid.replace(from, to);
+            fs::write(&path, &data).unwrap();
+            assert!(Puzzle::load(&path).is_err(), "{}", data);
+        }
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn skips_invalid_files() {
+        let valid = "retris puzzle 1\nname Test\ngoal tetris\npieces I\nboard\n#...\n";
+        let path = temp("dir", "1.txt", valid);
+        temp("dir", "2.txt", "retris puzzle 1\n");
+        temp("dir", "3.txt", valid);
+        let dir = path.parent().unwrap();
+        let loaded = Puzzle::load_dir(dir);
+        fs::remove_dir_all(dir).unwrap();
+
+        let (puzzles, errors) = loaded.unwrap();
+        assert_eq!(puzzles.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("2.txt"));
+    }
+
+    #[test]
+    fn solves_the_bundled_puzzles() {
+        use Input::*;
+        let solutions: [(&str, Vec<Input>); 5] = [
+            (
+                "01-tetris.txt",
+                vec![Rotate(Rotation::Clockwise), Column(9), HardDrop],
+            ),
+            ("02-square.txt", vec![HardDrop]),
+            (
+                "03-t-slot.txt",
+                vec![Rotate(Rotation::Half), Left, HardDrop],
+            ),
+            (
+                "04-clean.txt",
+                vec![Right, HardDrop, Right, HardDrop, Column(8), HardDrop],
+            ),
+            (
+                "05-t-spin.txt",
+                [Rotate(Rotation::CounterClockwise), Left, Left]
+                    .iter()
+                    .chain([Down; 20].iter())
+                    .chain([Rotate(Rotation::CounterClockwise), HardDrop].iter())
+                    .copied()
+                    .collect(),
+            ),
+        ];
+
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("puzzles");
+        let (puzzles, errors) = Puzzle::load_dir(&dir).unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(puzzles.len(), solutions.len());
+        for (name, inputs) in solutions.iter() {
+            let puzzle = Puzzle::load(&dir.join(name)).unwrap();
+            let mut state = puzzle.start();
+            for input in inputs.iter() {
+                assert_eq!(state.done(), None, "{}", name);
+                state.apply(*input);
+            }
+            assert_eq!(state.done(), Some(GameOver::Finished), "{}", name);
+        }
+    }
+}
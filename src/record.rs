@@ -0,0 +1,401 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use ncurses::*;
+use retris::{GameState, Input, Replay};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Read back the characters that are currently on the physical screen
+fn capture() -> Vec<Vec<chtype>> {
+    let (mut height, mut width) = (0, 0);
+    getmaxyx(curscr(), &mut height, &mut width);
+
+    (0..height)
+        .map(|y| (0..width).map(|x| mvwinch(curscr(), y, x)).collect())
+        .collect()
+}
+
+/// Translate a screen character, including the line drawing characters
+fn glyph(ch: chtype) -> char {
+    let c = (ch & A_CHARTEXT()) as u8 as char;
+    if ch & A_ALTCHARSET() == 0 {
+        return c;
+    }
+    match c {
+        'q' => '─',
+        'x' => '│',
+        'l' => '┌',
+        'k' => '┐',
+        'm' => '└',
+        'j' => '┘',
+        '0' => '█',
+        'a' => '▒',
+        _ => c,
+    }
+}
+
+/// Translate a screen character into plain ASCII
+fn ascii(ch: chtype) -> char {
+    match glyph(ch) {
+        '─' => '-',
+        '│' => '|',
+        '┌' | '┐' | '└' | '┘' => '+',
+        '█' => '#',
+        '▒' => ':',
+        c => c,
+    }
+}
+
+/// Get the ANSI escape sequence of a foreground and background color,
+/// the extended colors need a terminal with 256 colors
+pub fn sgr(fg: i16, bg: i16) -> String {
+    let color = |base: i16, color: i16| match color {
+        0..=7 => (base + color).to_string(),
+        color => format!("{};5;{}", base + 8, color),
+    };
+    format!("\x1b[{};{}m", color(30, fg), color(40, bg))
+}
+
+/// Render a row of the screen with ANSI escape sequences for the colors,
+/// ending with the default colors
+fn line(row: &[chtype]) -> String {
+    let mut line = String::new();
+    let mut color = 0;
+
+    for ch in row {
+        let pair = PAIR_NUMBER((*ch & A_COLOR()) as i32);
+        if pair != color {
+            if pair == 0 {
+                line.push_str("\x1b[0m");
+            } else {
+                let (mut fg, mut bg) = (0, 0);
+                pair_content(pair as i16, &mut fg, &mut bg);
+                line.push_str(&sgr(fg, bg));
+            }
+            color = pair;
+        }
+        line.push(glyph(*ch));
+    }
+    if color != 0 {
+        line.push_str("\x1b[0m");
+    }
+
+    line
+}
+
+/// Escape a string for a JSON document
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Recorder of the screen into an asciinema v2 cast file
+pub struct Cast {
+    /// The cast file
+    out: BufWriter<File>,
+    /// Time of the first frame, or None if the header is not written yet
+    start: Option<Instant>,
+    /// The previous frame, to skip frames without changes
+    last: String,
+}
+
+impl Cast {
+    /// Create a new cast recording in the specified file
+    pub fn new(file: File) -> Self {
+        Self {
+            out: BufWriter::new(file),
+            start: None,
+            last: String::new(),
+        }
+    }
+
+    /// Render the screen with ANSI escape sequences
+    fn render() -> String {
+        let lines: Vec<String> = capture().iter().map(|row| line(row)).collect();
+        format!("\x1b[H\x1b[2J{}", lines.join("\r\n"))
+    }
+
+    /// Write the header of the recording of a screen of the size
+    pub fn begin(&mut self, width: i32, height: i32) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        writeln!(
+            self.out,
+            "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}}}",
+            width, height, timestamp
+        )?;
+        self.start = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Append a frame at the time since the beginning of the recording,
+    /// unless it did not change
+    pub fn output(&mut self, time: Duration, frame: String) -> io::Result<()> {
+        if frame == self.last {
+            return Ok(());
+        }
+        writeln!(
+            self.out,
+            "[{:.6}, \"o\", {}]",
+            time.as_secs_f64(),
+            escape(&frame)
+        )?;
+        self.last = frame;
+
+        Ok(())
+    }
+
+    /// Append the current screen as a new frame
+    pub fn frame(&mut self) -> io::Result<()> {
+        if self.start.is_none() {
+            self.begin(COLS(), LINES())?;
+        }
+        let time = self.start.map(|start| start.elapsed()).unwrap_or_default();
+        self.output(time, Self::render())
+    }
+
+    /// Write all buffered frames to the file
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Dump of every frame as a numbered plain text file
+pub struct FrameDump {
+    /// The output directory
+    dir: PathBuf,
+    /// The current frame number
+    frame: usize,
+}
+
+impl FrameDump {
+    /// Dump frames into the specified directory
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, frame: 0 })
+    }
+
+    /// Write the current screen into the next frame file
+    pub fn frame(&mut self) -> io::Result<()> {
+        self.frame += 1;
+
+        let path = self.dir.join(format!("frame-{:06}.txt", self.frame));
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "frame {}", self.frame)?;
+        for line in capture() {
+            let line: String = line.into_iter().map(ascii).collect();
+            writeln!(out, "{}", line.trim_end())?;
+        }
+
+        out.flush()
+    }
+}
+
+/// Live view of the screen for spectators, like termcast: every frame
+/// sends the rows that changed with ANSI escape sequences, so it can be
+/// watched with `retris --watch` or any terminal and netcat
+pub struct Broadcast {
+    /// The socket that accepts new spectators
+    listener: TcpListener,
+    /// The connections of the spectators
+    watchers: Vec<TcpStream>,
+    /// The rows of the previous frame
+    last: Vec<String>,
+}
+
+impl Broadcast {
+    /// Listen for spectators on the TCP port
+    pub fn new(port: u16) -> io::Result<Self> {
+        let listener =
+            TcpListener::bind(("::", port)).or_else(|_| TcpListener::bind(("0.0.0.0", port)))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            watchers: Vec::new(),
+            last: Vec::new(),
+        })
+    }
+
+    /// Send the changes of the current screen to all spectators
+    pub fn frame(&mut self) -> io::Result<()> {
+        let rows: Vec<String> = capture().iter().map(|row| line(row)).collect();
+        let mut full = String::from("\x1b[?25l\x1b[H\x1b[2J");
+        let mut diff = String::new();
+        for (y, row) in rows.iter().enumerate() {
+            let _ = write!(full, "\x1b[{};1H{}", y + 1, row);
+            if self.last.get(y) != Some(row) {
+                let _ = write!(diff, "\x1b[{};1H{}", y + 1, row);
+            }
+        }
+        self.last = rows;
+
+        // A spectator that went away or cannot keep up is dropped, the
+        // game never waits for them
+        self.watchers
+            .retain_mut(|watcher| diff.is_empty() || watcher.write_all(diff.as_bytes()).is_ok());
+
+        // New spectators start with the whole screen
+        loop {
+            match self.listener.accept() {
+                Ok((mut watcher, _)) => {
+                    watcher.set_nonblocking(true)?;
+                    watcher.set_nodelay(true)?;
+                    if watcher.write_all(full.as_bytes()).is_ok() {
+                        self.watchers.push(watcher);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replay file of the inputs of the current game
+pub struct ReplayFile {
+    /// The output file
+    path: PathBuf,
+    /// The inputs of the current game
+    replay: Replay,
+    /// Is the current game recorded?
+    active: bool,
+}
+
+impl ReplayFile {
+    /// Record the inputs of each game into the specified file
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            replay: Replay::default(),
+            active: false,
+        }
+    }
+}
+
+/// Recorder of the rendered frames and inputs
+#[derive(Default)]
+pub struct Recorder {
+    /// Optional asciinema recording
+    pub cast: Option<Cast>,
+    /// Optional plain text frame dump
+    pub dump: Option<FrameDump>,
+    /// Optional replay of the inputs
+    pub replay: Option<ReplayFile>,
+    /// Optional live view for spectators
+    pub broadcast: Option<Broadcast>,
+    /// The first error that stopped the recording
+    pub error: Option<io::Error>,
+}
+
+impl Recorder {
+    /// Record the current screen
+    pub fn frame(&mut self) {
+        if let Some(cast) = self.cast.as_mut() {
+            if let Err(err) = cast.frame() {
+                self.cast = None;
+                self.stop(err);
+            }
+        }
+        if let Some(dump) = self.dump.as_mut() {
+            if let Err(err) = dump.frame() {
+                self.dump = None;
+                self.stop(err);
+            }
+        }
+        if let Some(broadcast) = self.broadcast.as_mut() {
+            if let Err(err) = broadcast.frame() {
+                self.broadcast = None;
+                self.stop(err);
+            }
+        }
+    }
+
+    /// Start recording the inputs of a new game
+    pub fn begin(&mut self, state: &GameState) {
+        if let Some(file) = self.replay.as_mut() {
+            file.replay = Replay::new(state.seed(), state.mode());
+            file.replay.set_size(state.height(), state.width());
+            file.replay.set_level(state.level());
+            file.replay.set_rules(state.rules());
+            file.active = true;
+        }
+    }
+
+    /// Do not record the inputs of a game that cannot be replayed, the
+    /// last recorded game is kept
+    pub fn skip(&mut self) {
+        if let Some(file) = self.replay.as_mut() {
+            file.active = false;
+        }
+    }
+
+    /// Record an input of the current game at the time of its clock,
+    /// which stands still while the game is paused
+    pub fn input(&mut self, time: Duration, input: Input) {
+        if let Some(file) = self.replay.as_mut().filter(|file| file.active) {
+            file.replay.push(time, input);
+        }
+    }
+
+    /// Write the replay of the current game, replacing the previous one
+    pub fn end(&mut self) {
+        if let Some(file) = self.replay.as_ref().filter(|file| file.active) {
+            if let Err(err) = file.replay.save(&file.path) {
+                self.replay = None;
+                self.stop(err);
+            }
+        }
+    }
+
+    /// Finish all recordings
+    pub fn finish(&mut self) {
+        if let Some(cast) = self.cast.as_mut() {
+            if let Err(err) = cast.flush() {
+                self.cast = None;
+                self.stop(err);
+            }
+        }
+    }
+
+    /// Remember the first recording error, the game goes on
+    fn stop(&mut self, err: io::Error) {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+    }
+}
@@ -0,0 +1,418 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::game::{Mode, Rules, GAME_HEIGHT, GAME_MAX, GAME_MIN, GAME_WIDTH, LEVEL_MAX};
+use crate::piece::Rotation;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The first line of a replay file
+const REPLAY_HEADER: &str = "retris replay 1";
+
+/// An input to the game, as it is recorded in a replay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    /// Move the piece to the left
+    Left,
+    /// Move the piece to the right
+    Right,
+    /// Move the piece one row down
+    Down,
+    /// Rotate the piece
+    Rotate(Rotation),
+    /// Swap the piece with the hold slot
+    Hold,
+    /// Move the piece to a column
+    Column(i32),
+    /// Drop the piece and lock it
+    HardDrop,
+    /// Let gravity pull the piece down by one row
+    Step,
+    /// Lock the piece on the ground after the lock delay
+    Lock,
+    /// Pause or resume the game
+    Pause,
+    /// End the game
+    Quit,
+    /// The time limit of the game mode ran out
+    TimeUp,
+    /// Queue rows of garbage from the opponent, with a hole in a column
+    Garbage(usize, i32),
+}
+
+impl Input {
+    /// Is this an input of the player that moves, rotates, holds or
+    /// drops the piece?
+    pub fn moves(self) -> bool {
+        matches!(
+            self,
+            Input::Left
+                | Input::Right
+                | Input::Down
+                | Input::Rotate(_)
+                | Input::Hold
+                | Input::Column(_)
+                | Input::HardDrop
+        )
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Left => write!(f, "left"),
+            Input::Right => write!(f, "right"),
+            Input::Down => write!(f, "down"),
+            Input::Rotate(Rotation::Clockwise) => write!(f, "rotate cw"),
+            Input::Rotate(Rotation::CounterClockwise) => write!(f, "rotate ccw"),
+            Input::Rotate(Rotation::Half) => write!(f, "rotate 180"),
+            Input::Hold => write!(f, "hold"),
+            Input::Column(column) => write!(f, "column {}", column),
+            Input::HardDrop => write!(f, "drop"),
+            Input::Step => write!(f, "step"),
+            Input::Lock => write!(f, "lock"),
+            Input::Pause => write!(f, "pause"),
+            Input::Quit => write!(f, "quit"),
+            Input::TimeUp => write!(f, "timeup"),
+            Input::Garbage(rows, hole) => write!(f, "garbage {} {}", rows, hole),
+        }
+    }
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let input = match (words.next(), words.next()) {
+            (Some("left"), None) => Input::Left,
+            (Some("right"), None) => Input::Right,
+            (Some("down"), None) => Input::Down,
+            (Some("rotate"), Some("cw")) => Input::Rotate(Rotation::Clockwise),
+            (Some("rotate"), Some("ccw")) => Input::Rotate(Rotation::CounterClockwise),
+            (Some("rotate"), Some("180")) => Input::Rotate(Rotation::Half),
+            (Some("hold"), None) => Input::Hold,
+            (Some("column"), Some(column)) => Input::Column(
+                column
+                    .parse()
+                    .map_err(|_| format!("invalid column: {}", column))?,
+            ),
+            (Some("drop"), None) => Input::HardDrop,
+            (Some("step"), None) => Input::Step,
+            (Some("lock"), None) => Input::Lock,
+            (Some("pause"), None) => Input::Pause,
+            (Some("quit"), None) => Input::Quit,
+            (Some("timeup"), None) => Input::TimeUp,
+            (Some("garbage"), Some(rows)) => {
+                let hole = words.next().unwrap_or_default();
+                Input::Garbage(
+                    rows.parse()
+                        .map_err(|_| format!("invalid rows: {}", rows))?,
+                    hole.parse()
+                        .ok()
+                        .filter(|hole| (0..GAME_MAX).contains(hole))
+                        .ok_or_else(|| format!("invalid hole: {}", hole))?,
+                )
+            }
+            _ => return Err(format!("unknown input: {}", s)),
+        };
+        match words.next() {
+            None => Ok(input),
+            Some(_) => Err(format!("unknown input: {}", s)),
+        }
+    }
+}
+
+/// The recorded inputs of a game that can be played back
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    /// The seed of the piece order
+    seed: u64,
+    /// The game mode
+    mode: Mode,
+    /// The number of rows and columns of the field
+    size: (i32, i32),
+    /// The level that the game started at
+    level: i32,
+    /// The rules of the game
+    rules: Rules,
+    /// The inputs and the time when they happened since the start
+    events: Vec<(Duration, Input)>,
+}
+
+impl Replay {
+    /// Start an empty replay of a game with the seed and mode
+    pub fn new(seed: u64, mode: Mode) -> Self {
+        Self {
+            seed,
+            mode,
+            size: (GAME_HEIGHT, GAME_WIDTH),
+            level: 1,
+            rules: Rules::default(),
+            events: Vec::new(),
+        }
+    }
+
+    /// The game mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The seed of the piece order
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The number of rows of the field
+    pub fn height(&self) -> i32 {
+        self.size.0
+    }
+
+    /// The number of columns of the field
+    pub fn width(&self) -> i32 {
+        self.size.1
+    }
+
+    /// Set the size of the field
+    pub fn set_size(&mut self, height: i32, width: i32) {
+        self.size = (height, width);
+    }
+
+    /// The level that the game started at
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    /// Set the level that the game started at
+    pub fn set_level(&mut self, level: i32) {
+        self.level = level;
+    }
+
+    /// The rules of the game
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Set the rules of the game
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+
+    /// The recorded inputs
+    pub fn events(&self) -> &[(Duration, Input)] {
+        &self.events
+    }
+
+    /// Record an input at the time since the start of the game
+    pub fn push(&mut self, time: Duration, input: Input) {
+        self.events.push((time, input));
+    }
+
+    /// Load a replay file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        let error = |n: usize, what: String| {
+            io::Error::new(ErrorKind::InvalidData, format!("line {}: {}", n + 1, what))
+        };
+
+        let mut lines = data.lines().enumerate();
+        match lines.next() {
+            Some((_, REPLAY_HEADER)) => {}
+            _ => return Err(error(0, "not a replay file".to_string())),
+        }
+        let seed = match lines.next() {
+            Some((n, line)) => line
+                .strip_prefix("seed ")
+                .and_then(|seed| seed.parse().ok())
+                .ok_or_else(|| error(n, "invalid seed".to_string()))?,
+            None => return Err(error(1, "missing seed".to_string())),
+        };
+
+        // Each line has the milliseconds since the previous input,
+        // after the optional game mode, field size, start level and rules
+        let mut replay = Self::new(seed, Mode::default());
+        let mut time = Duration::default();
+        for (n, line) in lines {
+            if let Some(mode) = line.strip_prefix("mode ") {
+                replay.mode = mode.parse().map_err(|err| error(n, err))?;
+                continue;
+            }
+            if let Some(size) = line.strip_prefix("size ") {
+                replay.size = size
+                    .split_once('x')
+                    .and_then(|(height, width)| Some((height.parse().ok()?, width.parse().ok()?)))
+                    .filter(|(height, width)| {
+                        (GAME_MIN..=GAME_MAX).contains(height)
+                            && (GAME_MIN..=GAME_MAX).contains(width)
+                    })
+                    .ok_or_else(|| error(n, format!("invalid size: {}", size)))?;
+                continue;
+            }
+            if let Some(level) = line.strip_prefix("level ") {
+                replay.level = level
+                    .parse()
+                    .ok()
+                    .filter(|level| (1..=LEVEL_MAX).contains(level))
+                    .ok_or_else(|| error(n, format!("invalid level: {}", level)))?;
+                continue;
+            }
+            if let Some(rules) = line.strip_prefix("rules ") {
+                replay.rules = rules.parse().map_err(|err| error(n, err))?;
+                continue;
+            }
+            let (delay, input) = line
+                .split_once(' ')
+                .ok_or_else(|| error(n, format!("invalid event: {}", line)))?;
+            let delay = delay
+                .parse()
+                .map_err(|_| error(n, format!("invalid time: {}", delay)))?;
+            time += Duration::from_millis(delay);
+            replay.push(time, input.parse().map_err(|err| error(n, err))?);
+        }
+
+        Ok(replay)
+    }
+
+    /// Write the replay to a file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut data = format!(
+            "{}\nseed {}\nmode {}\nsize {}x{}\nlevel {}\nrules {}\n",
+            REPLAY_HEADER, self.seed, self.mode, self.size.0, self.size.1, self.level, self.rules
+        );
+        let mut last = Duration::default();
+        for (time, input) in self.events.iter() {
+            let delay = time.saturating_sub(last).as_millis();
+            data.push_str(&format!("{} {}\n", delay, input));
+            last = *time;
+        }
+        fs::write(path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// Get a file for a test in the temporary directory
+    fn temp(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("retris-{}-{}", process::id(), name))
+    }
+
+    #[test]
+    fn formats_and_parses_inputs() {
+        let inputs = [
+            Input::Left,
+            Input::Right,
+            Input::Down,
+            Input::Rotate(Rotation::Clockwise),
+            Input::Rotate(Rotation::CounterClockwise),
+            Input::Rotate(Rotation::Half),
+            Input::Hold,
+            Input::Column(7),
+            Input::HardDrop,
+            Input::Step,
+            Input::Lock,
+            Input::Pause,
+            Input::Quit,
+            Input::TimeUp,
+            Input::Garbage(3, 9),
+        ];
+        for input in inputs.iter() {
+            assert_eq!(input.to_string().parse(), Ok(*input));
+        }
+        for invalid in ["", "rotate 90", "left 1", "column x", "garbage 2 -1"].iter() {
+            assert!(invalid.parse::<Input>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let mut replay = Replay::new(42, Mode::Sprint);
+        replay.set_size(24, 16);
+        replay.set_level(5);
+        replay.set_rules(Rules::Classic);
+        replay.push(Duration::from_millis(100), Input::Left);
+        replay.push(Duration::from_millis(100), Input::Rotate(Rotation::Half));
+        replay.push(Duration::from_millis(1250), Input::Garbage(2, 15));
+        replay.push(Duration::from_millis(2000), Input::HardDrop);
+
+        let path = temp("replay");
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.seed(), 42);
+        assert_eq!(loaded.mode(), Mode::Sprint);
+        assert_eq!((loaded.height(), loaded.width()), (24, 16));
+        assert_eq!(loaded.level(), 5);
+        assert_eq!(loaded.rules(), Rules::Classic);
+        assert_eq!(loaded.events(), replay.events());
+    }
+
+    #[test]
+    fn loads_replays_without_settings() {
+        // The settings of a new game are the defaults
+        let path = temp("defaults");
+        fs::write(
+            &path,
+            format!("{}\nseed 7\n0 left\n16 drop\n", REPLAY_HEADER),
+        )
+        .unwrap();
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!((loaded.height(), loaded.width()), (GAME_HEIGHT, GAME_WIDTH));
+        assert_eq!(loaded.rules(), Rules::Guideline);
+        assert_eq!(loaded.mode(), Mode::Marathon);
+        assert_eq!(loaded.level(), 1);
+        assert_eq!(
+            loaded.events(),
+            [
+                (Duration::from_millis(0), Input::Left),
+                (Duration::from_millis(16), Input::HardDrop)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_replays() {
+        let path = temp("invalid");
+        for data in [
+            "retris replay 0\nseed 7\n",
+            "retris replay 1\nseed x\n",
+            "retris replay 1\nseed 7\nsize 2x10\n",
+            "retris replay 1\nseed 7\nsize 101x10\n",
+            "retris replay 1\nseed 7\nlevel 0\n",
+            "retris replay 1\nseed 7\nx left\n",
+            "retris replay 1\nseed 7\n0 jump\n",
+        ]
+        .iter()
+        {
+            fs::write(&path, data).unwrap();
+            assert!(Replay::load(&path).is_err(), "{}", data);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of scores in the high score table
+pub const SCORES_MAX: usize = 10;
+
+/// An entry in the high score table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    /// The initials of the player
+    pub name: String,
+    /// The final score
+    pub score: i32,
+    /// The number of cleared lines
+    pub lines: usize,
+    /// The final level
+    pub level: i32,
+    /// The time of the game in seconds since the epoch
+    pub time: u64,
+}
+
+impl Score {
+    /// Create a new entry with the current time
+    pub fn new(name: &str, score: i32, lines: usize, level: i32) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            name: name.to_string(),
+            score,
+            lines,
+            level,
+            time,
+        }
+    }
+
+    /// Get the date of the game as YYYY-MM-DD
+    pub fn date(&self) -> String {
+        // Convert the days since the epoch to a civil date
+        let days = (self.time / 86400) as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let doe = days.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    /// Parse a line of the scores file
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        Some(Self {
+            score: fields.next()?.parse().ok()?,
+            name: fields.next()?.to_string(),
+            lines: fields.next()?.parse().ok()?,
+            level: fields.next()?.parse().ok()?,
+            time: fields.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// The persistent high score table
+#[derive(Debug, Default)]
+pub struct Scores {
+    /// The file that stores the scores
+    path: Option<PathBuf>,
+    /// The scores, best first
+    list: Vec<Score>,
+}
+
+impl Scores {
+    /// Get the default location of the scores file,
+    /// `$XDG_DATA_HOME/retris/scores` or `~/.local/share/retris/scores`
+    pub fn path() -> Option<PathBuf> {
+        let data = env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+        Some(data.join("retris").join("scores"))
+    }
+
+    /// Load the scores from the file, a missing file is an empty table
+    pub fn load(path: Option<PathBuf>) -> io::Result<Self> {
+        let mut scores = Self { path, list: vec![] };
+
+        if let Some(path) = scores.path.as_ref() {
+            match fs::read_to_string(path) {
+                Ok(data) => scores.list = data.lines().filter_map(Score::parse).collect(),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        scores.list.sort_by_key(|s| -s.score);
+        scores.list.truncate(SCORES_MAX);
+
+        Ok(scores)
+    }
+
+    /// Write the scores to the file
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let data: String = self
+            .list
+            .iter()
+            .map(|s| {
+                format!(
+                    "{}\t{}\t{}\t{}\t{}\n",
+                    s.score, s.name, s.lines, s.level, s.time
+                )
+            })
+            .collect();
+        fs::write(path, data)
+    }
+
+    /// Would the score make it into the table?
+    pub fn qualifies(&self, score: i32) -> bool {
+        score > 0 && (self.list.len() < SCORES_MAX || self.list.iter().any(|s| s.score < score))
+    }
+
+    /// Add a score to the table and return its position, if it made it
+    pub fn insert(&mut self, score: Score) -> Option<usize> {
+        if !self.qualifies(score.score) {
+            return None;
+        }
+        let pos = self
+            .list
+            .iter()
+            .position(|s| s.score < score.score)
+            .unwrap_or(self.list.len());
+        self.list.insert(pos, score);
+        self.list.truncate(SCORES_MAX);
+        Some(pos)
+    }
+
+    /// Iterate over the scores, best first
+    pub fn iter(&self) -> impl Iterator<Item = &Score> {
+        self.list.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /// Get a file for a test in the temporary directory
+    fn temp(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("retris-{}-{}", process::id(), name))
+    }
+
+    /// Create an entry with the score at the time
+    fn score(name: &str, score: i32, time: u64) -> Score {
+        Score {
+            name: name.to_string(),
+            score,
+            lines: 0,
+            level: 1,
+            time,
+        }
+    }
+
+    /// Get the names of the scores, best first
+    fn names(scores: &Scores) -> Vec<&str> {
+        scores.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_the_scores() {
+        let mut scores = Scores::default();
+        assert!(!scores.qualifies(0));
+        assert_eq!(scores.insert(score("AAA", 0, 0)), None);
+        assert_eq!(scores.insert(score("BBB", 200, 0)), Some(0));
+        assert_eq!(scores.insert(score("CCC", 300, 0)), Some(0));
+        assert_eq!(scores.insert(score("DDD", 100, 0)), Some(2));
+
+        // A tie goes below the earlier score
+        assert_eq!(scores.insert(score("EEE", 200, 0)), Some(2));
+        assert_eq!(names(&scores), ["CCC", "BBB", "EEE", "DDD"]);
+    }
+
+    #[test]
+    fn keeps_the_best_scores() {
+        let mut scores = Scores::default();
+        for i in 1..=SCORES_MAX as i32 {
+            assert_eq!(scores.insert(score("AAA", i * 100, 0)), Some(0));
+        }
+        assert_eq!(scores.iter().count(), SCORES_MAX);
+
+        // A full table only takes scores above the lowest one
+        assert!(!scores.qualifies(50));
+        assert!(!scores.qualifies(100));
+        assert!(scores.qualifies(101));
+        assert_eq!(scores.insert(score("BBB", 100, 0)), None);
+        assert_eq!(scores.insert(score("CCC", 550, 0)), Some(5));
+        assert_eq!(scores.iter().count(), SCORES_MAX);
+        assert_eq!(scores.iter().last().map(|s| s.score), Some(200));
+    }
+
+    #[test]
+    fn parses_scores() {
+        assert_eq!(
+            Score::parse("1200\tABC\t12\t3\t951782400"),
+            Some(Score {
+                name: "ABC".to_string(),
+                score: 1200,
+                lines: 12,
+                level: 3,
+                time: 951_782_400,
+            })
+        );
+        assert_eq!(Score::parse(""), None);
+        assert_eq!(Score::parse("1200\tABC\t12\t3"), None);
+        assert_eq!(Score::parse("many\tABC\t12\t3\t951782400"), None);
+        assert_eq!(Score::parse("1200\tABC\t-1\t3\t951782400"), None);
+    }
+
+    #[test]
+    fn loads_and_saves_scores() {
+        let path = temp("scores");
+        fs::write(
+            &path,
+            "100\tAAA\t1\t1\t0\ncorrupt line\n300\tBBB\t3\t1\t0\n200\tCCC\t2\t1\t0\n",
+        )
+        .unwrap();
+
+        // Corrupt lines are skipped and the scores are ranked
+        let scores = Scores::load(Some(path.clone())).unwrap();
+        assert_eq!(names(&scores), ["BBB", "CCC", "AAA"]);
+        scores.save().unwrap();
+        let loaded = Scores::load(Some(path.clone())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.list, scores.list);
+
+        // A missing file is an empty table
+        let scores = Scores::load(Some(path)).unwrap();
+        assert_eq!(scores.iter().count(), 0);
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(score("AAA", 1, 0).date(), "1970-01-01");
+        assert_eq!(score("AAA", 1, 951_782_400).date(), "2000-02-29");
+        assert_eq!(score("AAA", 1, 1_577_836_799).date(), "2019-12-31");
+        assert_eq!(score("AAA", 1, 1_577_836_800).date(), "2020-01-01");
+    }
+}
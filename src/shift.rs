@@ -0,0 +1,243 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use retris::Input;
+use std::time::{Duration, Instant};
+
+/// The default delayed auto shift, before a held key starts to repeat
+pub const DAS: Duration = Duration::from_millis(170);
+/// The default auto repeat rate, the time between two shifts
+pub const ARR: Duration = Duration::from_millis(50);
+
+/// The longest time between two key repeats of the terminal
+const REPEAT: Duration = Duration::from_millis(100);
+/// The longest delay before the terminal starts to repeat a held key
+const HOLD: Duration = Duration::from_millis(700);
+
+/// A key that is held down
+struct Held {
+    /// The input of the key
+    input: Input,
+    /// When the key was pressed
+    pressed: Instant,
+    /// When the terminal sent the key the last time
+    seen: Instant,
+    /// The time between key repeats, once the terminal repeats the key
+    interval: Option<Duration>,
+    /// When the piece was shifted the last time
+    shifted: Instant,
+}
+
+impl Held {
+    /// The time without a key repeat that releases the key
+    fn timeout(&self) -> Duration {
+        self.interval
+            .map_or(HOLD, |interval| (2 * interval).min(REPEAT))
+    }
+}
+
+/// Delayed auto shift of the piece while a key is held down.
+///
+/// Terminals only report key presses, so a held key is detected by the
+/// key repeats of the terminal, and it is released when they stop.  The
+/// shifts are timed by the game instead of the terminal, but they cannot
+/// start before the terminal repeats the key.
+pub struct AutoShift {
+    /// The delay before a held key starts to shift the piece
+    das: Duration,
+    /// The time between two shifts
+    arr: Duration,
+    /// The key that is currently held
+    held: Option<Held>,
+}
+
+impl AutoShift {
+    /// Create a new auto shift with the delay and repeat rate
+    pub fn new(das: Duration, arr: Duration) -> Self {
+        Self {
+            das,
+            arr,
+            held: None,
+        }
+    }
+
+    /// A key was pressed at the time, returns true if it should shift the
+    /// piece and false for a key repeat that is replaced by the auto shift
+    pub fn press(&mut self, now: Instant, input: Input) -> bool {
+        if let Some(held) = self.held.as_mut() {
+            let gap = now - held.seen;
+            if held.input == input && gap <= held.timeout() {
+                held.seen = now;
+                if gap < REPEAT {
+                    held.interval = Some(gap);
+                    return false;
+                }
+
+                // Another press, or the first repeat after the delay
+                held.shifted = now;
+                return true;
+            }
+        }
+
+        self.held = Some(Held {
+            input,
+            pressed: now,
+            seen: now,
+            interval: None,
+            shifted: now,
+        });
+        true
+    }
+
+    /// Release the held key, any other key stops the key repeat
+    pub fn release(&mut self) {
+        self.held = None;
+    }
+
+    /// Handle the input of a key at the time, or of no key if the terminal
+    /// did not send one. Key repeats of left and right are replaced by the
+    /// auto shift and any other key releases the held key.
+    pub fn input(&mut self, now: Instant, input: Option<Input>, key: bool) -> Option<Input> {
+        match input {
+            Some(dir @ Input::Left) | Some(dir @ Input::Right) => {
+                if self.press(now, dir) {
+                    Some(dir)
+                } else {
+                    self.tick(now)
+                }
+            }
+            Some(input) => {
+                self.release();
+                Some(input)
+            }
+            None => {
+                if key {
+                    self.release();
+                }
+                self.tick(now)
+            }
+        }
+    }
+
+    /// Get the next automatic shift of the held key, if it is due at
+    /// the time
+    pub fn tick(&mut self, now: Instant) -> Option<Input> {
+        let held = self.held.as_mut()?;
+
+        // The terminal stopped repeating the key, it was released
+        if now - held.seen > held.timeout() {
+            self.held = None;
+            return None;
+        }
+
+        // Wait for the key repeats, the delay, and the repeat rate
+        if held.interval.is_none() || now - held.pressed < self.das || now - held.shifted < self.arr
+        {
+            return None;
+        }
+        held.shifted = now;
+        Some(held.input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send the inputs of the terminal at the milliseconds since the
+    /// start, None if no key was sent, and get the shifts of the piece
+    fn play(events: &[(u64, Option<Input>)]) -> Vec<(u64, Input)> {
+        let mut shift = AutoShift::new(DAS, ARR);
+        let start = Instant::now();
+        events
+            .iter()
+            .filter_map(|(ms, input)| {
+                let now = start + Duration::from_millis(*ms);
+                let shifted = shift.input(now, *input, input.is_some())?;
+                Some((*ms, shifted))
+            })
+            .collect()
+    }
+
+    /// The key repeats of the terminal every 40ms, with no input in
+    /// between every 10ms
+    fn repeats(input: Input, from: u64, to: u64) -> Vec<(u64, Option<Input>)> {
+        (from..to)
+            .step_by(10)
+            .map(|ms| (ms, Some(input).filter(|_| (ms - from) % 40 < 10)))
+            .collect()
+    }
+
+    #[test]
+    fn waits_for_the_delay() {
+        // A single press shifts once, the repeats of the terminal only
+        // shift after the delay and then at the repeat rate
+        let shifts = play(&repeats(Input::Left, 0, 400));
+        let times: Vec<u64> = shifts.iter().map(|(ms, _)| *ms).collect();
+        assert_eq!(times, [0, 170, 220, 270, 320, 370]);
+        assert!(shifts.iter().all(|(_, input)| *input == Input::Left));
+    }
+
+    #[test]
+    fn repeats_at_the_rate() {
+        let mut shift = AutoShift::new(Duration::from_millis(100), Duration::from_millis(20));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(shift.press(at(0), Input::Right));
+        assert!(!shift.press(at(30), Input::Right));
+        assert!(!shift.press(at(60), Input::Right));
+        assert!(!shift.press(at(90), Input::Right));
+        assert_eq!(shift.tick(at(95)), None);
+        assert_eq!(shift.tick(at(100)), Some(Input::Right));
+        assert_eq!(shift.tick(at(110)), None);
+        assert!(!shift.press(at(120), Input::Right));
+        assert_eq!(shift.tick(at(120)), Some(Input::Right));
+        assert_eq!(shift.tick(at(140)), Some(Input::Right));
+    }
+
+    #[test]
+    fn releases_the_key() {
+        // The terminal stopped repeating the key
+        let mut events = repeats(Input::Left, 0, 200);
+        events.extend((200..400).step_by(10).map(|ms| (ms, None)));
+        let shifts = play(&events);
+        assert_eq!(shifts.last(), Some(&(220, Input::Left)));
+
+        // Another key releases it
+        let mut events = repeats(Input::Left, 0, 200);
+        events.push((200, Some(Input::HardDrop)));
+        events.extend((210..400).step_by(10).map(|ms| (ms, None)));
+        let shifts = play(&events);
+        assert_eq!(shifts.last(), Some(&(200, Input::HardDrop)));
+    }
+
+    #[test]
+    fn reverses_the_direction() {
+        // The other direction shifts at once and waits for its own delay
+        let mut events = repeats(Input::Left, 0, 200);
+        events.extend(repeats(Input::Right, 200, 400));
+        let shifts = play(&events);
+        let right: Vec<u64> = shifts
+            .iter()
+            .filter(|(_, input)| *input == Input::Right)
+            .map(|(ms, _)| *ms)
+            .collect();
+        assert_eq!(right, [200, 370]);
+        assert!(shifts
+            .iter()
+            .all(|(ms, input)| (*ms < 200) == (*input == Input::Left)));
+    }
+}
@@ -0,0 +1,182 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The number of tetromino types
+pub const PIECE_TYPES: usize = 7;
+
+/// The statistics of a game
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// The number of locked pieces of each tetromino type, by id
+    pieces: [usize; PIECE_TYPES],
+    /// The number of singles, doubles, triples and tetrises
+    clears: [usize; 4],
+    /// The number of inputs of the player that moved, rotated, held
+    /// or dropped the piece
+    inputs: usize,
+}
+
+impl Stats {
+    /// Count a locked piece and the rows that it cleared
+    pub(crate) fn place(&mut self, id: u8, rows: usize) {
+        if let Some(count) = (id as usize)
+            .checked_sub(1)
+            .and_then(|i| self.pieces.get_mut(i))
+        {
+            *count += 1;
+        }
+        if rows > 0 {
+            self.clears[rows.min(4) - 1] += 1;
+        }
+    }
+
+    /// Count an input of the player
+    pub(crate) fn input(&mut self) {
+        self.inputs += 1;
+    }
+
+    /// The number of locked pieces
+    pub fn pieces(&self) -> usize {
+        self.pieces.iter().sum()
+    }
+
+    /// The number of locked pieces of the tetromino type with the id
+    pub fn piece(&self, id: u8) -> usize {
+        (id as usize)
+            .checked_sub(1)
+            .and_then(|i| self.pieces.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The number of clears of one to four rows at once
+    pub fn clears(&self, rows: usize) -> usize {
+        rows.checked_sub(1)
+            .and_then(|i| self.clears.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The number of inputs of the player
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// The locked pieces per second during the time of the game
+    pub fn pps(&self, time: Duration) -> f64 {
+        if time.as_secs_f64() > 0.0 {
+            self.pieces() as f64 / time.as_secs_f64()
+        } else {
+            0.0
+        }
+    }
+
+    /// The inputs per locked piece
+    pub fn kpp(&self) -> f64 {
+        match self.pieces() {
+            0 => 0.0,
+            pieces => self.inputs as f64 / pieces as f64,
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    /// The counts of the pieces and clears, followed by the inputs
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for count in self.pieces.iter().chain(self.clears.iter()) {
+            write!(f, "{} ", count)?;
+        }
+        write!(f, "{}", self.inputs)
+    }
+}
+
+impl FromStr for Stats {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let counts = s
+            .split_whitespace()
+            .map(|count| count.parse())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| format!("invalid stats: {}", s))?;
+        if counts.len() != PIECE_TYPES + 5 {
+            return Err(format!("invalid stats: {}", s));
+        }
+        let mut stats = Self::default();
+        stats.pieces.copy_from_slice(&counts[..PIECE_TYPES]);
+        stats
+            .clears
+            .copy_from_slice(&counts[PIECE_TYPES..PIECE_TYPES + 4]);
+        stats.inputs = counts[PIECE_TYPES + 4];
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_pieces_and_clears() {
+        let mut stats = Stats::default();
+        stats.place(1, 4);
+        stats.place(1, 0);
+        stats.place(7, 1);
+        stats.place(0, 2);
+        stats.input();
+        stats.input();
+        assert_eq!(stats.pieces(), 3);
+        assert_eq!((stats.piece(1), stats.piece(7), stats.piece(8)), (2, 1, 0));
+        assert_eq!(
+            (stats.clears(1), stats.clears(2), stats.clears(4)),
+            (1, 1, 1)
+        );
+        assert_eq!(stats.clears(0), 0);
+        assert_eq!(stats.pps(Duration::from_secs(2)), 1.5);
+        assert_eq!(stats.pps(Duration::default()), 0.0);
+        assert_eq!(stats.kpp(), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn formats_and_parses_stats() {
+        let mut stats = Stats::default();
+        for id in 1..=PIECE_TYPES as u8 {
+            for rows in 0..id as usize {
+                stats.place(id, rows % 5);
+                stats.input();
+            }
+        }
+        let text = stats.to_string();
+        assert_eq!(text, "1 2 3 4 5 6 7 7 5 4 3 28");
+        let parsed: Stats = text.parse().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.pieces(), stats.pieces());
+        assert_eq!(parsed.clears(4), stats.clears(4));
+        assert_eq!(parsed.inputs(), stats.inputs());
+    }
+
+    #[test]
+    fn rejects_invalid_stats() {
+        assert!("".parse::<Stats>().is_err());
+        assert!("1 2 3 4 5 6 7 6 5 4 3".parse::<Stats>().is_err());
+        assert!("1 2 3 4 5 6 7 6 5 4 3 28 1".parse::<Stats>().is_err());
+        assert!("1 2 3 4 5 6 7 6 5 4 3 -1".parse::<Stats>().is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::menu::toggle;
+use crate::{KEY_NEWLINE, KEY_QUIT, KEY_SPACE};
+use ncurses::*;
+use std::env;
+
+/// The width of the status window
+const STATUS_WIDTH: i32 = 24;
+/// The width of the window of the upcoming pieces
+pub const QUEUE_WIDTH: i32 = 8;
+/// The height of the status window, which can be taller than the field
+pub const STATUS_HEIGHT: i32 = 22;
+
+const KEY_ASCII: i32 = 97;
+const KEY_COLORS: i32 = 99;
+
+/// How blocks and borders are rendered
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Style {
+    /// Draw blocks with colors
+    pub colors: bool,
+    /// Only use plain ASCII instead of the line drawing characters
+    pub ascii: bool,
+}
+
+impl Style {
+    /// Use all features of the terminal
+    pub fn new() -> Self {
+        Self {
+            colors: has_colors(),
+            ascii: false,
+        }
+    }
+
+    /// Safe rendering without colors and line drawing characters
+    pub fn safe() -> Self {
+        Self {
+            colors: false,
+            ascii: true,
+        }
+    }
+
+    /// Get the character of a block pixel
+    pub fn pixel(&self, c: char, id: u8) -> u32 {
+        match (self.colors, self.ascii) {
+            (true, false) => ACS_BLOCK() | COLOR_PAIR(id.into()),
+            (true, true) => c as u32 | COLOR_PAIR(id.into()),
+            (false, _) => c as u32,
+        }
+    }
+
+    /// Get the character of a ghost pixel, where the piece would land
+    pub fn ghost(&self) -> u32 {
+        if self.ascii {
+            '.' as u32
+        } else {
+            ACS_CKBOARD() | A_DIM()
+        }
+    }
+
+    /// Get the character of a pixel in a cleared row
+    pub fn flash(&self) -> u32 {
+        if self.ascii {
+            '=' as u32
+        } else {
+            ACS_BLOCK() | A_BOLD()
+        }
+    }
+
+    /// Get the character of the meter of pending garbage
+    pub fn meter(&self) -> u32 {
+        match (self.colors, self.ascii) {
+            (_, true) => '>' as u32,
+            (true, false) => ACS_BLOCK() | COLOR_PAIR(7),
+            (false, false) => ACS_BLOCK(),
+        }
+    }
+
+    /// Draw a border around the window
+    pub fn border(&self, window: WINDOW) {
+        if self.ascii {
+            let (v, h, c) = ('|' as u32, '-' as u32, '+' as u32);
+            wborder(window, v, v, h, h, c, c, c, c);
+        } else {
+            box_(window, 0, 0);
+        }
+    }
+}
+
+/// A problem with the terminal and a suggestion how to fix it
+struct Problem {
+    what: String,
+    fix: &'static str,
+}
+
+/// Get the width of the windows of one player next to each other: the
+/// status, the field and the upcoming pieces
+pub fn player_width(width: i32) -> i32 {
+    status_width() + 1 + width + 4 + QUEUE_WIDTH
+}
+
+/// Get the width of the status window next to a field, with a margin
+pub fn status_width() -> i32 {
+    STATUS_WIDTH + 2
+}
+
+/// Detect common problems with the terminal environment, for the
+/// players with fields of the specified size
+fn problems(height: i32, width: i32, players: i32) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    // The minimum terminal size to fit the game and status windows,
+    // a single field is in the center of the screen
+    let min_height = (height + 2).max(STATUS_HEIGHT) + 1;
+    let min_width = if players > 1 {
+        players * (player_width(width) + 1)
+    } else {
+        2 * status_width() + width + 2
+    };
+
+    let term = env::var("TERM").unwrap_or_default();
+    if term == "dumb" || term == "unknown" {
+        problems.push(Problem {
+            what: format!("TERM={} cannot draw the game", term),
+            fix: "Set TERM to your terminal type, e.g. xterm-256color",
+        });
+    }
+
+    if !has_colors() {
+        problems.push(Problem {
+            what: "The terminal has no colors".to_string(),
+            fix: "Blocks are drawn as letters instead",
+        });
+    }
+
+    if ACS_BLOCK() & A_ALTCHARSET() == 0 {
+        problems.push(Problem {
+            what: "The terminal has no line drawing characters".to_string(),
+            fix: "Press a to draw with plain ASCII",
+        });
+    }
+
+    // Try the locale from the environment and go back to the default
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty());
+    if let Some(locale) = locale {
+        if setlocale(LcCategory::ctype, "").is_empty() {
+            problems.push(Problem {
+                what: format!("The locale {} is not installed", locale),
+                fix: "Install it or set LANG=C.UTF-8",
+            });
+        }
+        setlocale(LcCategory::ctype, "C");
+    }
+
+    if LINES() < min_height || COLS() < min_width {
+        problems.push(Problem {
+            what: format!(
+                "The terminal has {}x{} characters, {}x{} are needed",
+                COLS(),
+                LINES(),
+                min_width,
+                min_height
+            ),
+            fix: "Resize the window or use a smaller font",
+        });
+    }
+
+    problems
+}
+
+/// Show a diagnostics screen if the terminal has any problems with
+/// the players and fields of the specified size.
+/// Returns false if the player decided to quit.
+pub fn diagnose(style: &mut Style, height: i32, width: i32, players: i32) -> bool {
+    let mut found = problems(height, width, players);
+    if found.is_empty() {
+        return true;
+    }
+
+    cbreak();
+    keypad(stdscr(), true);
+
+    loop {
+        clear();
+        mvaddstr(0, 0, "rETRIS found problems with your terminal:");
+        let mut y = 2;
+        for problem in found.iter() {
+            mvaddstr(y, 1, &format!("* {}", problem.what));
+            mvaddstr(y + 1, 3, problem.fix);
+            y += 3;
+        }
+        mvaddstr(y, 1, &format!("c: colors [{}]", toggle(style.colors)));
+        mvaddstr(
+            y + 1,
+            1,
+            &format!("a: ASCII only [{}]", toggle(style.ascii)),
+        );
+        mvaddstr(y + 3, 1, "space: start   q: quit");
+        refresh();
+
+        match getch() {
+            KEY_QUIT => return false,
+            KEY_SPACE | KEY_NEWLINE => break,
+            KEY_COLORS if has_colors() => style.colors = !style.colors,
+            KEY_ASCII => style.ascii = !style.ascii,
+            KEY_RESIZE => found = problems(height, width, players),
+            _ => {}
+        }
+    }
+
+    clear();
+    refresh();
+
+    true
+}
@@ -0,0 +1,349 @@
+//
+// Copyright (c) 2019 Reyk Floeter <contact@reykfloeter.com>
+//
+// Permission to use, copy, modify, and distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+// WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+// MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+// ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+// WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+// ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+// OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+//
+
+use crate::bell::{Bell, Event};
+use crate::clock::Clock;
+use crate::config::{Action, Config, Keymap};
+use crate::net::{Connection, Message};
+use crate::record::Recorder;
+use crate::shift::AutoShift;
+use crate::term::{player_width, status_width, Style};
+use crate::{movement, Game, Setup, Timers};
+use ncurses::*;
+use rand::{thread_rng, Rng};
+use retris::{GameOver, GameState, Input};
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+
+/// One of the two players of a versus game
+struct Player {
+    /// The game of the player
+    state: GameState,
+    /// The windows of the player
+    game: Game,
+    /// The name that is shown below the status
+    name: &'static str,
+    /// The key bindings of a player at this keyboard, none for the
+    /// player at the other end of a network game
+    keymap: Option<Keymap>,
+    /// The auto shift of a held key
+    shift: AutoShift,
+    /// The timers of the falling piece
+    timers: Timers,
+}
+
+impl Player {
+    /// Render the game of the player
+    fn render(&mut self, now: Duration) {
+        self.game.render(&self.state, now);
+        self.game.note(self.name);
+    }
+}
+
+/// Get the winner once the game is decided, or None while it goes on.
+/// The first player whose stack overflows or who leaves loses, unless
+/// the other one finished by reaching the goal first.  When the time
+/// is up, the higher score wins after both games ran out of time.
+fn winner(done: [Option<GameOver>; 2], scores: [i32; 2]) -> Option<Option<usize>> {
+    let lost = |done| {
+        matches!(
+            done,
+            Some(GameOver::LockOut | GameOver::BlockOut | GameOver::TopOut | GameOver::Quit)
+        )
+    };
+    let finished = |done| done == Some(GameOver::Finished);
+    if finished(done[0]) || lost(done[1]) {
+        Some(Some(0))
+    } else if finished(done[1]) || lost(done[0]) {
+        Some(Some(1))
+    } else if done.iter().all(Option::is_some) {
+        Some(match scores[0].cmp(&scores[1]) {
+            Ordering::Greater => Some(0),
+            Ordering::Less => Some(1),
+            Ordering::Equal => None,
+        })
+    } else {
+        None
+    }
+}
+
+/// Show the result over the middle of the screen
+fn announce(style: Style, keymap: &Keymap, text: &str, rematch: bool) {
+    let quit = format!("{}: quit", keymap.name(Action::Quit));
+    let help = if rematch {
+        format!("{}: rematch  {}", keymap.name(Action::Restart), quit)
+    } else {
+        quit
+    };
+    let (height, width) = (5, help.len().max(text.len()) as i32 + 4);
+    let window = newwin(height, width, 8, getmaxx(curscr()) / 2 - width / 2);
+
+    mvwaddstr(window, 1, (width - text.len() as i32) / 2, text);
+    mvwaddstr(window, 3, 2, &help);
+
+    style.border(window);
+    wrefresh(window);
+    delwin(window);
+}
+
+/// Play a game of two players side by side, on the same keyboard or
+/// against a player at the other end of the link.  Returns true if
+/// the players want a rematch, which is only offered on one keyboard.
+pub fn versus(
+    style: Style,
+    config: &Config,
+    setup: Setup,
+    mut link: Option<&mut Connection>,
+    recorder: &mut Recorder,
+    bell: &mut Bell,
+) -> bool {
+    // Both players get the same pieces in independent queues
+    let seed = setup.seed.unwrap_or_else(|| thread_rng().gen());
+    let center = getmaxx(curscr()) / 2;
+    let lefts = [center - player_width(setup.width), center + 1];
+    let [left, right] = Keymap::versus();
+    let players = match link {
+        Some(_) => [("YOU", Some(config.keymap.clone())), ("OPPONENT", None)],
+        None => [("PLAYER 1", Some(left)), ("PLAYER 2", Some(right))],
+    };
+    let mut players: Vec<Player> = players
+        .iter()
+        .zip(lefts.iter())
+        .map(|((name, keymap), left)| {
+            let mut state = GameState::with_size(seed, setup.height, setup.width);
+            state.set_lock_delay(config.lock_delay);
+            state.set_mode(setup.mode);
+            let (height, width) = (state.height(), state.width());
+            let xoff = left + status_width() + 1;
+            let help = keymap.as_ref().unwrap_or(&config.keymap);
+            let mut game = Game::with_layout(style, help, height, width, *left, xoff);
+            game.columns = false;
+            game.help = keymap.is_some();
+            game.ghost = config.ghost;
+            Player {
+                state,
+                game,
+                name,
+                keymap: keymap.clone(),
+                shift: AutoShift::new(config.das, config.arr),
+                timers: Timers::default(),
+            }
+        })
+        .collect();
+    let mut clock = Clock::new();
+    let mut shown = 0;
+    for player in players.iter_mut() {
+        player.render(clock.now());
+    }
+    recorder.frame();
+
+    loop {
+        // The players share the keyboard, each key belongs to one of them
+        let key = wgetch(*players[0].game);
+        let action = |player: &Player| player.keymap.as_ref()?.action(key);
+        let who = players.iter().position(|player| action(player).is_some());
+        let action = who.and_then(|number| action(&players[number]));
+        let paused = players[0].state.paused();
+        match action {
+            Some(Action::Quit) => {
+                if let Some(link) = link.as_mut() {
+                    let _ = link.send(&Message::Bye);
+                }
+                return false;
+            }
+            Some(Action::Restart) if link.is_none() && !paused => return true,
+            Some(Action::Pause) if link.is_none() => {
+                if paused {
+                    clock.resume();
+                } else {
+                    clock.pause();
+                }
+                for player in players.iter_mut() {
+                    player.state.apply(Input::Pause);
+                }
+            }
+            _ => {}
+        }
+
+        // Receive the inputs and fields of the remote player, and the
+        // garbage for us
+        let mut remote = Vec::new();
+        let mut incoming = Vec::new();
+        if let Some(link) = link.as_mut() {
+            loop {
+                match link.recv() {
+                    Ok(Some(Message::Garbage(rows, hole))) => incoming.push((rows, hole)),
+                    Ok(Some(message)) => remote.push(message),
+                    Ok(None) => break,
+                    Err(_) => {
+                        // The other player left or the connection broke
+                        players[1].state.gameover(GameOver::Quit);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let now = clock.now();
+        let mut changed = key != ERR;
+        for number in 0..players.len() {
+            let player = &mut players[number];
+            let mut rows = None;
+            let mut gravity = false;
+            let mut sent = Vec::new();
+            let mut input = None;
+
+            if player.keymap.is_some() {
+                // Garbage raises the stack before the next move, it needs
+                // a hole in the field
+                let width = player.state.width();
+                for (rows, hole) in incoming
+                    .drain(..)
+                    .filter(|(_, hole)| (0..width).contains(hole))
+                {
+                    sent.push(Input::Garbage(rows, hole));
+                    player.state.apply(Input::Garbage(rows, hole));
+                }
+
+                let resets = player.state.resets();
+                input = match action {
+                    Some(action) if who == Some(number) && !paused => movement(action),
+                    _ => None,
+                };
+                input = player
+                    .shift
+                    .input(Instant::now(), input, who == Some(number));
+                if let Some(input) = input {
+                    sent.push(input);
+                    rows = player.state.apply(input);
+                }
+                let mut record = |input| sent.push(input);
+                gravity =
+                    player
+                        .timers
+                        .update(&mut player.state, now, resets, &mut rows, &mut record);
+            } else {
+                // The remote game is played with the inputs of the other end,
+                // its field corrects the game if it went out of sync
+                for message in remote.drain(..) {
+                    match message {
+                        Message::Input(input) => {
+                            if let Some(cleared) = player.state.apply(input) {
+                                rows = Some(cleared.max(rows.unwrap_or_default()));
+                            }
+                        }
+                        Message::Board(cells) => {
+                            player.state.set_cells(&cells);
+                        }
+                        Message::Bye => player.state.gameover(GameOver::Quit),
+                        Message::Setup(_) | Message::Garbage(..) => {}
+                    }
+                    changed = true;
+                }
+            }
+
+            // Animate the cleared rows while the time of the game stands still
+            if rows.is_some_and(|rows| rows > 0) {
+                clock.pause();
+                player
+                    .game
+                    .sweep(&player.state, config.line_clear, recorder);
+                clock.resume();
+            }
+
+            match rows {
+                None => {}
+                Some(0) => bell.push(Event::Lock),
+                Some(1..=3) => bell.push(Event::Clear),
+                Some(_) => bell.push(Event::Tetris),
+            }
+            changed |= input.is_some() || gravity || rows.is_some();
+
+            // Send the inputs, and the field after a piece locked
+            if let (Some(link), false) = (link.as_mut(), sent.is_empty()) {
+                let mut messages: Vec<Message> = sent.into_iter().map(Message::Input).collect();
+                if rows.is_some() {
+                    messages.push(Message::Board(player.state.cells().to_vec()));
+                }
+                let lost = messages.iter().any(|message| link.send(message).is_err());
+                if lost {
+                    players[1].state.gameover(GameOver::Quit);
+                }
+            }
+
+            // Clearing more than one row sends the garbage that it did not
+            // cancel to the opponent, with the hole in a random column.
+            // The remote player gets it over the link and sends it back
+            // as an input.
+            let garbage = rows.map_or(0, |_| players[number].state.sent());
+            if garbage > 0 && players[number].keymap.is_some() {
+                let opponent = &mut players[1 - number].state;
+                let hole = thread_rng().gen_range(0, opponent.width());
+                match link.as_mut() {
+                    Some(link) => {
+                        let _ = link.send(&Message::Garbage(garbage, hole));
+                    }
+                    None => opponent.garbage(garbage, hole),
+                }
+            }
+        }
+
+        // Render output if anything happened, or every tenth of a second
+        // to update the time
+        changed |= shown != now.as_millis() / 100;
+        shown = now.as_millis() / 100;
+        if changed {
+            for player in players.iter_mut() {
+                player.render(now);
+            }
+            recorder.frame();
+        }
+
+        let done = [players[0].state.done(), players[1].state.done()];
+        let winner = match winner(done, [players[0].state.score(), players[1].state.score()]) {
+            Some(winner) => winner,
+            None => {
+                bell.ring();
+                continue;
+            }
+        };
+        let text = match (winner, link.is_some()) {
+            (None, _) => "DRAW!".to_string(),
+            (Some(0), true) => "YOU WIN!".to_string(),
+            (Some(_), true) => "YOU LOSE!".to_string(),
+            (Some(number), false) => format!("{} WINS!", players[number].name),
+        };
+
+        bell.push(Event::GameOver);
+        bell.ring();
+        let keymap = players[0].keymap.clone().unwrap_or_default();
+        announce(style, &keymap, &text, link.is_none());
+        recorder.frame();
+
+        loop {
+            match keymap.action(wgetch(*players[0].game)) {
+                Some(Action::Quit) => {
+                    if let Some(link) = link.as_mut() {
+                        let _ = link.send(&Message::Bye);
+                    }
+                    return false;
+                }
+                Some(Action::Restart) if link.is_none() => return true,
+                _ => {}
+            }
+        }
+    }
+}